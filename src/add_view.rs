@@ -1,14 +1,58 @@
-use crate::play_buttons::{ButtonMessage};
-use crate::sound_player::{Sound};
+use crate::effects::EffectKind;
+use crate::play_buttons::{ButtonMessage, ButtonOptions, RetriggerMode};
+use crate::sound_player::{Layer, PlaybackMode, Sound, SoundFile, VariationMode};
 
-use crate::{sound_player, Message, WindowSettings};
+use crate::{Message, WindowSettings};
 use iced::{
-    button, text_input, Align, Button, Column, Element, Length, Row, Text, TextInput,
+    button, pick_list, text_input, Align, Button, Column, Element, Length, PickList, Row,
+    Text, TextInput,
 };
 use std::path::{Path, PathBuf};
 
 use std::sync::{Arc, Mutex};
 
+// the inputs of one layer of the button being added
+#[derive(Default)]
+struct LayerInput {
+    path_input: text_input::State,
+    gain_input: text_input::State,
+    offset_input: text_input::State,
+    remove_button: button::State,
+    path: String,
+    gain: String,
+    offset: String,
+}
+
+impl LayerInput {
+    fn layer(&self) -> Layer {
+        Layer {
+            file: SoundFile::new(self.path.trim().to_string()),
+            gain: self.gain.trim().parse().ok().filter(|gain: &f64| gain.is_finite()).unwrap_or(0.0),
+            offset: parse_secs(&self.offset).unwrap_or(0.0),
+        }
+    }
+}
+
+// the inputs of one effect of the button being added
+struct EffectInput {
+    kind: EffectKind,
+    inputs: Vec<text_input::State>,
+    values: Vec<String>,
+    remove_button: button::State,
+}
+
+impl EffectInput {
+    fn new(kind: EffectKind) -> Self {
+        let params = kind.params().len();
+        Self {
+            kind,
+            inputs: (0..params).map(|_| Default::default()).collect(),
+            values: vec!["".to_string(); params],
+            remove_button: Default::default(),
+        }
+    }
+}
+
 #[derive(Default)]
 pub(crate) struct AddView {
     pub(crate) video_settings: Arc<Mutex<WindowSettings>>,
@@ -18,8 +62,36 @@ pub(crate) struct AddView {
     add_confirm_button: button::State,
     path_output1: text_input::State,
     name_output1: text_input::State,
+    start_input: text_input::State,
+    end_input: text_input::State,
+    fade_in_input: text_input::State,
+    fade_out_input: text_input::State,
+    choke_group_input: text_input::State,
+    retrigger_list: pick_list::State<RetriggerMode>,
+    mode_list: pick_list::State<PlaybackMode>,
+    variation_list: pick_list::State<VariationMode>,
+    hotkey_input: text_input::State,
+    speed_input: text_input::State,
+    pitch_input: text_input::State,
+    random_pitch_input: text_input::State,
+    add_layer_button: button::State,
+    layers: Vec<LayerInput>,
+    add_effect_list: pick_list::State<EffectKind>,
+    effects: Vec<EffectInput>,
     temp_path: String,
     temp_name: String,
+    temp_start: String,
+    temp_end: String,
+    temp_mode: PlaybackMode,
+    temp_variation: VariationMode,
+    temp_fade_in: String,
+    temp_fade_out: String,
+    temp_choke_group: String,
+    temp_retrigger: RetriggerMode,
+    temp_hotkey: String,
+    temp_speed: String,
+    temp_pitch: String,
+    temp_random_pitch: String,
 }
 
 #[derive(Debug, Clone)]
@@ -28,7 +100,27 @@ pub(crate) enum AddViewMessage {
     PathOk(String),
     PathNotOk(String),
     NameChange(String),
-    ButtonAdded(Sound, String, bool), //sound, name, ok
+    StartChange(String),
+    EndChange(String),
+    ModeSelected(PlaybackMode),
+    VariationSelected(VariationMode),
+    FadeInChange(String),
+    FadeOutChange(String),
+    ChokeGroupChange(String),
+    RetriggerSelected(RetriggerMode),
+    HotkeyChange(String),
+    SpeedChange(String),
+    PitchChange(String),
+    RandomPitchChange(String),
+    AddLayerPressed,
+    RemoveLayerPressed(usize),
+    LayerPathChange(usize, String),
+    LayerGainChange(usize, String),
+    LayerOffsetChange(usize, String),
+    EffectAdded(EffectKind),
+    RemoveEffectPressed(usize),
+    EffectParamChange(usize, usize, String), //effect, parameter and value
+    ButtonAdded, //adds a button made from the current inputs if the path is ok
     FileDropped(PathBuf),
     AddPressed,
 }
@@ -42,21 +134,115 @@ pub(crate) fn check_filetype(path: &str) -> bool{
              }
          }
      }
-     return false
+     false
+}
+
+// a button plays one of several files, their paths are entered separated by ;
+fn split_paths(val: &str) -> Vec<String> {
+    val.split(';')
+        .map(|path| path.trim())
+        .filter(|path| !path.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn check_paths(val: &str) -> bool {
+    let paths = split_paths(val);
+    !paths.is_empty() && paths.iter().all(|path| check_filetype(path))
+}
+
+// parses an optional time in seconds, empty or invalid input means no time was set
+fn parse_secs(val: &str) -> Option<f64> {
+    val.trim().parse::<f64>().ok().filter(|secs| secs.is_finite() && *secs >= 0.0)
 }
 
 impl AddView {
+    // the sound described by the current inputs
+    fn sound(&self) -> Sound {
+        Sound {
+            start: parse_secs(&self.temp_start),
+            end: parse_secs(&self.temp_end),
+            mode: self.temp_mode,
+            variation: self.temp_variation,
+            fade_in: parse_secs(&self.temp_fade_in).unwrap_or(0.0),
+            fade_out: parse_secs(&self.temp_fade_out).unwrap_or(0.0),
+            layers: self.layers.iter().map(LayerInput::layer).collect(),
+            speed: self.temp_speed.trim().parse().ok().filter(|speed| *speed > 0.0).unwrap_or(1.0),
+            pitch: self.temp_pitch.trim().parse().ok().filter(|pitch: &f64| pitch.is_finite()).unwrap_or(0.0),
+            random_pitch: self
+                .temp_random_pitch
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|range| range.is_finite())
+                .map_or(0.0, f64::abs),
+            effects: self
+                .effects
+                .iter()
+                .map(|effect| {
+                    //nan and inf parse as numbers but are no usable parameters
+                    let values: Vec<Option<f64>> = effect
+                        .values
+                        .iter()
+                        .map(|val| val.trim().parse().ok().filter(|val: &f64| val.is_finite()))
+                        .collect();
+                    effect.kind.effect(&values)
+                })
+                .collect(),
+            ..Sound::new(split_paths(&self.temp_path))
+        }
+    }
+
+    // the button can only be added once its files and the files of all its layers exist
+    fn can_confirm(&self) -> bool {
+        self.allow_confirm
+            && self
+                .layers
+                .iter()
+                .all(|layer| check_filetype(layer.path.trim()))
+    }
+
+    // the options described by the current inputs
+    fn options(&self) -> ButtonOptions {
+        ButtonOptions {
+            choke_group: self.temp_choke_group.trim().to_string(),
+            retrigger: self.temp_retrigger,
+            hotkey: self.temp_hotkey.trim().to_uppercase(),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.temp_path = "".to_string();
+        self.temp_name = "".to_string();
+        self.temp_start = "".to_string();
+        self.temp_end = "".to_string();
+        self.temp_mode = Default::default();
+        self.temp_variation = Default::default();
+        self.temp_fade_in = "".to_string();
+        self.temp_fade_out = "".to_string();
+        self.temp_choke_group = "".to_string();
+        self.temp_retrigger = Default::default();
+        self.temp_hotkey = "".to_string();
+        self.temp_speed = "".to_string();
+        self.temp_pitch = "".to_string();
+        self.temp_random_pitch = "".to_string();
+        self.layers.clear();
+        self.effects.clear();
+        self.allow_confirm = false;
+        self.is_being_added = false;
+    }
 
     pub fn update(&mut self, msg: AddViewMessage) -> Option<ButtonMessage> {
         let mut ret_val = None;
         match msg {
-            AddViewMessage::ButtonAdded(sound, name, ok) => {
-                if ok {
-                    ret_val = Some(ButtonMessage::ButtonAdded(sound, name));
-                    self.temp_path = "".to_string();
-                    self.temp_name = "".to_string();
-                    self.allow_confirm = false;
-                    self.is_being_added = false;
+            AddViewMessage::ButtonAdded => {
+                if self.can_confirm() {
+                    ret_val = Some(ButtonMessage::ButtonAdded(
+                        Box::new(self.sound()),
+                        self.options(),
+                        self.temp_name.to_owned(),
+                    ));
+                    self.reset();
                 }
             }
 
@@ -74,19 +260,101 @@ impl AddView {
                 self.temp_name = name;
             }
 
+            AddViewMessage::StartChange(val) => {
+                self.temp_start = val;
+            }
+
+            AddViewMessage::EndChange(val) => {
+                self.temp_end = val;
+            }
+
+            AddViewMessage::ModeSelected(mode) => {
+                self.temp_mode = mode;
+            }
+
+            AddViewMessage::VariationSelected(variation) => {
+                self.temp_variation = variation;
+            }
+
+            AddViewMessage::FadeInChange(val) => {
+                self.temp_fade_in = val;
+            }
+
+            AddViewMessage::FadeOutChange(val) => {
+                self.temp_fade_out = val;
+            }
+
+            AddViewMessage::ChokeGroupChange(val) => {
+                self.temp_choke_group = val;
+            }
+
+            AddViewMessage::RetriggerSelected(mode) => {
+                self.temp_retrigger = mode;
+            }
+
+            AddViewMessage::HotkeyChange(val) => {
+                self.temp_hotkey = val;
+            }
+
+            AddViewMessage::SpeedChange(val) => {
+                self.temp_speed = val;
+            }
+
+            AddViewMessage::PitchChange(val) => {
+                self.temp_pitch = val;
+            }
+
+            AddViewMessage::RandomPitchChange(val) => {
+                self.temp_random_pitch = val;
+            }
+
+            AddViewMessage::AddLayerPressed => {
+                self.layers.push(Default::default());
+            }
+
+            AddViewMessage::RemoveLayerPressed(index) => {
+                self.layers.remove(index);
+            }
+
+            AddViewMessage::LayerPathChange(index, val) => {
+                self.layers[index].path = val;
+            }
+
+            AddViewMessage::LayerGainChange(index, val) => {
+                self.layers[index].gain = val;
+            }
+
+            AddViewMessage::LayerOffsetChange(index, val) => {
+                self.layers[index].offset = val;
+            }
+
+            AddViewMessage::EffectAdded(kind) => {
+                self.effects.push(EffectInput::new(kind));
+            }
+
+            AddViewMessage::RemoveEffectPressed(index) => {
+                self.effects.remove(index);
+            }
+
+            AddViewMessage::EffectParamChange(index, param, val) => {
+                self.effects[index].values[param] = val;
+            }
+
             AddViewMessage::CancelButtonPressed => {
-                self.temp_path = "".to_string();
-                self.temp_name = "".to_string();
-                self.allow_confirm = false;
-                self.is_being_added = false;
+                self.reset();
             }
             AddViewMessage::AddPressed => {
                 self.is_being_added = true;
             }
             AddViewMessage::FileDropped(path_buf) => {
                 if let Some(str) = path_buf.to_str(){
-                    self.temp_path = String::from(str);
-                    self.allow_confirm = check_filetype(str);
+                    //files dropped while adding a button become more files of that button
+                    if self.is_being_added && !self.temp_path.trim().is_empty() {
+                        self.temp_path = format!("{}; {}", self.temp_path.trim(), str);
+                    } else {
+                        self.temp_path = String::from(str);
+                    }
+                    self.allow_confirm = check_paths(&self.temp_path);
                     self.is_being_added = true;
                 }
             }
@@ -100,40 +368,106 @@ impl AddView {
 
         if self.is_being_added && width != 0 && height != 0 {
             if self.is_being_added {
-                let add_button = if self.allow_confirm {
+                let add_button = if self.can_confirm() {
                     Button::new(&mut self.add_confirm_button, Text::new("confirm")).on_press(
-                        Message::AddView(AddViewMessage::ButtonAdded(
-                            sound_player::Sound::new(self.temp_path.clone()),
-                            self.temp_name.to_owned(),
-                            self.allow_confirm,
-                        )),
+                        Message::AddView(AddViewMessage::ButtonAdded),
                     )
                 } else {
-                    Button::new(&mut self.add_confirm_button, Text::new("please enter the path to a supported sound file"))
+                    Button::new(&mut self.add_confirm_button, Text::new("please enter the paths to supported sound files"))
                 };
 
+                //one row per effect, in the order they are applied
+                let mut effect_rows = Column::new();
+                for (index, effect) in self.effects.iter_mut().enumerate() {
+                    let mut row = Row::new()
+                        .align_items(Align::Center)
+                        .push(Text::new(effect.kind.to_string()).width(Length::from(((width / 100) * 14) as u16)));
+                    let params = effect.kind.params().iter();
+                    let inputs = effect.inputs.iter_mut().zip(effect.values.iter());
+                    for (param, ((name, _), (input, value))) in params.zip(inputs).enumerate() {
+                        row = row.push(
+                            TextInput::new(input, name, value, move |val| {
+                                Message::AddView(AddViewMessage::EffectParamChange(index, param, val))
+                            })
+                            .width(Length::from(((width / 100) * 20) as u16)),
+                        );
+                    }
+                    effect_rows = effect_rows.push(row.push(
+                        Button::new(&mut effect.remove_button, Text::new("X"))
+                            .on_press(Message::AddView(AddViewMessage::RemoveEffectPressed(index))),
+                    ));
+                }
+
+                //one row per layer
+                let mut layer_rows = Column::new();
+                for (index, layer) in self.layers.iter_mut().enumerate() {
+                    layer_rows = layer_rows.push(
+                        Row::new()
+                            .align_items(Align::Center)
+                            .push(
+                                TextInput::new(
+                                    &mut layer.path_input,
+                                    "layer filepath",
+                                    &layer.path,
+                                    move |val| Message::AddView(AddViewMessage::LayerPathChange(index, val)),
+                                )
+                                .width(Length::from(((width / 100) * 50) as u16)),
+                            )
+                            .push(
+                                TextInput::new(
+                                    &mut layer.gain_input,
+                                    "gain (dB, optional)",
+                                    &layer.gain,
+                                    move |val| Message::AddView(AddViewMessage::LayerGainChange(index, val)),
+                                )
+                                .width(Length::from(((width / 100) * 12) as u16)),
+                            )
+                            .push(
+                                TextInput::new(
+                                    &mut layer.offset_input,
+                                    "offset (seconds, optional)",
+                                    &layer.offset,
+                                    move |val| Message::AddView(AddViewMessage::LayerOffsetChange(index, val)),
+                                )
+                                .width(Length::from(((width / 100) * 12) as u16)),
+                            )
+                            .push(
+                                Button::new(&mut layer.remove_button, Text::new("X"))
+                                    .on_press(Message::AddView(AddViewMessage::RemoveLayerPressed(index))),
+                            ),
+                    );
+                }
+
                 Column::new()
                     .push(
-                        TextInput::new(
-                            &mut self.path_output1,
-                            "enter the filepath here",
-                            &self.temp_path,
-                            |val| {
-                                if check_filetype(&val){
-                                    Message::AddView(AddViewMessage::PathOk(val))
-                                } else {
-                                    Message::AddView(AddViewMessage::PathNotOk(val))
-                                }
-                            },
-                        )
-                        .width(Length::from(((width / 100) * 80) as u16))
-                        .on_submit(Message::AddView(
-                            AddViewMessage::ButtonAdded(
-                                sound_player::Sound::new(self.temp_path.clone()),
-                                self.temp_name.to_owned(),
-                                self.allow_confirm,
+                        Row::new()
+                            .push(
+                                TextInput::new(
+                                    &mut self.path_output1,
+                                    "enter the filepath here, separate several files with ;",
+                                    &self.temp_path,
+                                    |val| {
+                                        if check_paths(&val){
+                                            Message::AddView(AddViewMessage::PathOk(val))
+                                        } else {
+                                            Message::AddView(AddViewMessage::PathNotOk(val))
+                                        }
+                                    },
+                                )
+                                .width(Length::from(((width / 100) * 60) as u16))
+                                .on_submit(Message::AddView(
+                                    AddViewMessage::ButtonAdded,
+                                )),
+                            )
+                            .push(
+                                PickList::new(
+                                    &mut self.variation_list,
+                                    &VariationMode::ALL[..],
+                                    Some(self.temp_variation),
+                                    |variation| Message::AddView(AddViewMessage::VariationSelected(variation)),
+                                )
+                                .width(Length::from(((width / 100) * 20) as u16)),
                             ),
-                        )),
                     )
                     .push(
                         TextInput::new(
@@ -144,15 +478,134 @@ impl AddView {
                         )
                         .width(Length::from(((width / 100) * 80) as u16))
                         .on_submit(Message::AddView(
-                            AddViewMessage::ButtonAdded(
-                                sound_player::Sound::new(self.temp_path.clone()),
-                                self.temp_name.to_owned(),
-                                self.allow_confirm,
-                            ),
+                            AddViewMessage::ButtonAdded,
                         )),
                     )
+                    .push(
+                        Row::new()
+                            .push(
+                                TextInput::new(
+                                    &mut self.start_input,
+                                    "start at (seconds, optional)",
+                                    &self.temp_start,
+                                    |val| Message::AddView(AddViewMessage::StartChange(val)),
+                                )
+                                .width(Length::from(((width / 100) * 40) as u16)),
+                            )
+                            .push(
+                                TextInput::new(
+                                    &mut self.end_input,
+                                    "end at (seconds, optional)",
+                                    &self.temp_end,
+                                    |val| Message::AddView(AddViewMessage::EndChange(val)),
+                                )
+                                .width(Length::from(((width / 100) * 40) as u16)),
+                            )
+                            .push(
+                                PickList::new(
+                                    &mut self.mode_list,
+                                    &PlaybackMode::ALL[..],
+                                    Some(self.temp_mode),
+                                    |mode| Message::AddView(AddViewMessage::ModeSelected(mode)),
+                                )
+                                .width(Length::from(((width / 100) * 20) as u16)),
+                            ),
+                    )
+                    .push(
+                        Row::new()
+                            .push(
+                                TextInput::new(
+                                    &mut self.fade_in_input,
+                                    "fade in (seconds, optional)",
+                                    &self.temp_fade_in,
+                                    |val| Message::AddView(AddViewMessage::FadeInChange(val)),
+                                )
+                                .width(Length::from(((width / 100) * 40) as u16)),
+                            )
+                            .push(
+                                TextInput::new(
+                                    &mut self.fade_out_input,
+                                    "fade out (seconds, optional)",
+                                    &self.temp_fade_out,
+                                    |val| Message::AddView(AddViewMessage::FadeOutChange(val)),
+                                )
+                                .width(Length::from(((width / 100) * 40) as u16)),
+                            )
+                            .push(
+                                TextInput::new(
+                                    &mut self.hotkey_input,
+                                    "hotkey (optional)",
+                                    &self.temp_hotkey,
+                                    |val| Message::AddView(AddViewMessage::HotkeyChange(val)),
+                                )
+                                .width(Length::from(((width / 100) * 20) as u16)),
+                            ),
+                    )
+                    .push(
+                        Row::new()
+                            .push(
+                                TextInput::new(
+                                    &mut self.speed_input,
+                                    "speed (optional, 2 is twice as fast and an octave higher)",
+                                    &self.temp_speed,
+                                    |val| Message::AddView(AddViewMessage::SpeedChange(val)),
+                                )
+                                .width(Length::from(((width / 100) * 34) as u16)),
+                            )
+                            .push(
+                                TextInput::new(
+                                    &mut self.pitch_input,
+                                    "pitch (semitones, optional)",
+                                    &self.temp_pitch,
+                                    |val| Message::AddView(AddViewMessage::PitchChange(val)),
+                                )
+                                .width(Length::from(((width / 100) * 23) as u16)),
+                            )
+                            .push(
+                                TextInput::new(
+                                    &mut self.random_pitch_input,
+                                    "random pitch +- (semitones, optional)",
+                                    &self.temp_random_pitch,
+                                    |val| Message::AddView(AddViewMessage::RandomPitchChange(val)),
+                                )
+                                .width(Length::from(((width / 100) * 23) as u16)),
+                            ),
+                    )
+                    .push(
+                        Row::new()
+                            .push(
+                                TextInput::new(
+                                    &mut self.choke_group_input,
+                                    "choke group (optional), starting this button stops the others in its group",
+                                    &self.temp_choke_group,
+                                    |val| Message::AddView(AddViewMessage::ChokeGroupChange(val)),
+                                )
+                                .width(Length::from(((width / 100) * 50) as u16)),
+                            )
+                            .push(
+                                PickList::new(
+                                    &mut self.retrigger_list,
+                                    &RetriggerMode::ALL[..],
+                                    Some(self.temp_retrigger),
+                                    |mode| Message::AddView(AddViewMessage::RetriggerSelected(mode)),
+                                )
+                                .width(Length::from(((width / 100) * 30) as u16)),
+                            ),
+                    )
+                    .push(layer_rows)
+                    .push(effect_rows)
                     .push(
                         Row::new().push(add_button).push(
+                            Button::new(&mut self.add_layer_button, Text::new("add layer"))
+                                .on_press(Message::AddView(AddViewMessage::AddLayerPressed)),
+                        ).push(Text::new("add effect:")).push(
+                            PickList::new(
+                                &mut self.add_effect_list,
+                                &EffectKind::ALL[..],
+                                None,
+                                |kind| Message::AddView(AddViewMessage::EffectAdded(kind)),
+                            ),
+                        ).push(
                             Button::new(&mut self.cancel_button, Text::new("cancel"))
                                 .on_press(Message::AddView(AddViewMessage::CancelButtonPressed)),
                         ),
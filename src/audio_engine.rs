@@ -0,0 +1,537 @@
+use crate::audio_settings::{AudioSettings, DeviceLossPolicy};
+use crate::backend::{self, AudioBackend, Output};
+use crate::mic::{Ducking, Passthrough};
+use crate::mixer::Mixer;
+use std::sync::Arc;
+
+// device checks in a row an open device may be missing from the list before its output counts as lost
+// some hosts never report an unplugged device to its stream, so the list is all there is to go by
+const MISSED_CHECKS_UNTIL_LOST: u32 = 3;
+
+// whether an output plays on the device selected for it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OutputStatus {
+    Connected,
+    // the selected device is missing, the default device plays instead
+    FallenBack,
+    // no device plays the output, its voices wait where they are
+    Lost,
+}
+
+// an output whose voices keep their place while the device playing them changes
+#[derive(Default)]
+struct OpenOutput {
+    // the selected device, empty for the default device and None before the first sync
+    dev_name: Option<String>,
+    mixer: Arc<Mixer>,
+    device: Option<Box<dyn Output>>,
+    // the selected device was missing and the default device got opened instead
+    fallen_back: bool,
+    // device checks in a row the open device was missing from the list
+    missed_checks: u32,
+}
+
+impl OpenOutput {
+    // opens the selected device or, if it is missing and the policy allows it, the default device
+    // a device known to be missing is not tried
+    fn open(&mut self, backend: &dyn AudioBackend, policy: DeviceLossPolicy, present: bool) {
+        //close the old stream before opening the new one, some devices can only be opened once
+        self.device = None;
+        self.fallen_back = false;
+        self.missed_checks = 0;
+        let dev_name = self.dev_name.clone().unwrap_or_default();
+        if present {
+            self.device = backend.open_output(&dev_name, self.mixer.clone());
+        }
+        if self.device.is_none() && !dev_name.is_empty() && policy == DeviceLossPolicy::FallBack {
+            self.device = backend.open_output("", self.mixer.clone());
+            self.fallen_back = self.device.is_some();
+        }
+    }
+
+    // whether the device named in the list of available devices is there
+    fn is_present(&self, available: &[String]) -> Option<bool> {
+        let dev_name = self.dev_name.as_ref()?;
+        if dev_name.is_empty() {
+            Some(!available.is_empty())
+        } else {
+            Some(available.contains(dev_name))
+        }
+    }
+
+    fn status(&self) -> OutputStatus {
+        match &self.device {
+            None => OutputStatus::Lost,
+            Some(_) if self.fallen_back => OutputStatus::FallenBack,
+            Some(_) => OutputStatus::Connected,
+        }
+    }
+}
+
+// a removed output takes the voices still playing on it along
+impl Drop for OpenOutput {
+    fn drop(&mut self) {
+        self.mixer.close();
+    }
+}
+
+// keeps the selected output devices open so playing a sound only has to add a voice to their mixers
+// also plays the microphone on the output chosen for it while its passthrough is unmuted
+pub(crate) struct AudioEngine {
+    backend: Arc<dyn AudioBackend>,
+    // one per output bus of the settings, in the same order
+    outputs: Vec<OpenOutput>,
+    // the passthrough and the mixer of the output it plays on
+    mic: Option<(Arc<Mixer>, Passthrough)>,
+    // the input and output the passthrough last failed to start on
+    // it is only tried again on a settings change or a device check, not on every press
+    mic_failed: Option<(String, Arc<Mixer>)>,
+    // kept by the engine so a restarted passthrough continues ducked or not
+    ducking: Arc<Ducking>,
+}
+
+impl Default for AudioEngine {
+    fn default() -> Self {
+        Self::new(backend::from_env())
+    }
+}
+
+impl AudioEngine {
+    pub(crate) fn new(backend: Arc<dyn AudioBackend>) -> Self {
+        Self {
+            backend,
+            outputs: vec![],
+            mic: None,
+            mic_failed: None,
+            ducking: Default::default(),
+        }
+    }
+
+    pub(crate) fn backend(&self) -> Arc<dyn AudioBackend> {
+        self.backend.clone()
+    }
+
+    // opens the outputs that were added or whose device name changed
+    // and starts, stops or adjusts the microphone passthrough
+    pub(crate) fn sync_devices(&mut self, settings: &AudioSettings) {
+        self.sync_outputs(settings);
+        self.sync_mic(settings, true);
+    }
+
+    fn sync_outputs(&mut self, settings: &AudioSettings) {
+        self.outputs.truncate(settings.outputs.len());
+        self.outputs.resize_with(settings.outputs.len(), Default::default);
+        for (output, bus) in self.outputs.iter_mut().zip(settings.outputs.iter()) {
+            Self::sync_output(&*self.backend, output, &bus.dev_name, settings.device_loss);
+        }
+    }
+
+    // closes the output at that index, the outputs after it move up to match the settings again
+    pub(crate) fn remove_output(&mut self, index: usize) {
+        if index < self.outputs.len() {
+            self.outputs.remove(index);
+        }
+    }
+
+    fn sync_output(
+        backend: &dyn AudioBackend,
+        output: &mut OpenOutput,
+        dev_name: &str,
+        policy: DeviceLossPolicy,
+    ) {
+        if output.dev_name.as_deref() != Some(dev_name) {
+            output.dev_name = Some(dev_name.to_string());
+            output.open(backend, policy, true);
+        }
+    }
+
+    // called regularly with the names of the output devices that are available right now
+    // closes the outputs whose device went away and reconnects them once it is back
+    // returns whether the status of an output changed
+    pub(crate) fn check_devices(&mut self, available: &[String], settings: &AudioSettings) -> bool {
+        let policy = settings.device_loss;
+        let mut changed = false;
+        for output in self.outputs.iter_mut() {
+            let present = match output.is_present(available) {
+                Some(present) => present,
+                None => continue,
+            };
+            if present {
+                output.missed_checks = 0;
+            } else {
+                output.missed_checks += 1;
+            }
+            let device_lost = output.device.as_ref().is_some_and(|device| device.is_lost());
+
+            //an open device missing from the list once may just be busy, some hosts leave those out
+            let reopen = match output.status() {
+                OutputStatus::Connected => device_lost || output.missed_checks >= MISSED_CHECKS_UNTIL_LOST,
+                OutputStatus::FallenBack => device_lost || present || policy == DeviceLossPolicy::Pause,
+                OutputStatus::Lost => {
+                    present || (policy == DeviceLossPolicy::FallBack && !available.is_empty())
+                }
+            };
+            if reopen {
+                let status = output.status();
+                output.open(&*self.backend, policy, present);
+                changed |= output.status() != status;
+            }
+        }
+        //a passthrough whose input went away or could not be started gets another try, its input may be back
+        if self.mic.as_ref().is_some_and(|(_, mic)| mic.is_done()) {
+            self.mic = None;
+        }
+        if self.mic.is_none() && !settings.mic_muted {
+            self.sync_mic(settings, true);
+        }
+        changed
+    }
+
+    // the status of every output, in the order of the output buses
+    pub(crate) fn output_status(&self) -> Vec<OutputStatus> {
+        self.outputs.iter().map(OpenOutput::status).collect()
+    }
+
+    // playback waits while an output has no device, its voices would not move on
+    pub(crate) fn is_output_lost(&self) -> bool {
+        self.outputs.iter().any(|output| output.status() == OutputStatus::Lost)
+    }
+
+    // the mixers of the outputs as they are open right now, in the order of the output buses
+    pub(crate) fn mixers(&self) -> Vec<Arc<Mixer>> {
+        self.outputs.iter().map(|output| output.mixer.clone()).collect()
+    }
+
+    // retry is false when only playing a sound, an input that could not be opened is not tried again then
+    fn sync_mic(&mut self, settings: &AudioSettings, retry: bool) {
+        if settings.ducking {
+            self.ducking.configure(settings.duck_depth, settings.duck_attack, settings.duck_release);
+        } else {
+            self.ducking.configure(0, settings.duck_attack, settings.duck_release);
+        }
+
+        //a muted microphone is not recorded at all
+        if settings.mic_muted {
+            self.mic = None;
+            return;
+        }
+
+        let mixer = match self.outputs.get(settings.mic_output()) {
+            Some(output) => output.mixer.clone(),
+            None => {
+                self.mic = None;
+                return;
+            }
+        };
+        let volume = settings.mic_volume as f32 / 100.0;
+        match &self.mic {
+            Some((mic_mixer, mic)) if mic.dev_name == settings.mic_dev_name && Arc::ptr_eq(mic_mixer, &mixer) => {
+                mic.set_volume(volume)
+            }
+            _ => {
+                let failed_before = self.mic_failed.as_ref().is_some_and(|(dev_name, failed_mixer)| {
+                    *dev_name == settings.mic_dev_name && Arc::ptr_eq(failed_mixer, &mixer)
+                });
+                if failed_before && !retry {
+                    return;
+                }
+                self.mic = None;
+                self.mic = Passthrough::start(
+                    settings.mic_dev_name.clone(),
+                    &*self.backend,
+                    &mixer,
+                    self.ducking.clone(),
+                    volume,
+                )
+                .map(|mic| (mixer.clone(), mic));
+                self.mic_failed = match self.mic {
+                    Some(_) => None,
+                    None => Some((settings.mic_dev_name.clone(), mixer)),
+                };
+            }
+        }
+    }
+
+    // whether any sound is playing, the microphone passthrough gets ducked while one is
+    pub(crate) fn set_ducked(&self, ducked: bool) {
+        self.ducking.set_active(ducked);
+    }
+
+    // returns the mixers of all outputs after opening their devices if needed
+    // None while one of them has no device
+    pub(crate) fn outputs(&mut self, settings: &AudioSettings) -> Option<Vec<Arc<Mixer>>> {
+        self.sync_outputs(settings);
+        self.sync_mic(settings, false);
+        if self.is_output_lost() {
+            return None;
+        }
+        Some(self.mixers())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_settings::OutputBus;
+    use crate::mic::InputSource;
+    use crate::mixer::{BoxedSource, SAMPLE_RATE};
+    use crate::recording::RecordingBackend;
+    use crate::sample_cache::DecodedSound;
+    use std::collections::VecDeque;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    fn settings_with_mic(dev_name: &str) -> AudioSettings {
+        let mut settings = AudioSettings::default();
+        settings.outputs = vec![OutputBus {
+            dev_name: dev_name.to_string(),
+            volume: 100,
+            muted: false,
+        }];
+        settings.mic_output = 0;
+        settings.mic_muted = false;
+        settings.mic_volume = 100;
+        settings.ducking = true;
+        settings.duck_depth = 20;
+        settings.duck_attack = 0;
+        settings.duck_release = 0;
+        settings
+    }
+
+    // the samples the passthrough played next, silence while the input had nothing yet is left out
+    fn played(backend: &RecordingBackend) -> Vec<f32> {
+        //give the input time to record
+        thread::sleep(Duration::from_millis(20));
+        backend.advance(256);
+        backend
+            .take_recorded("out")
+            .into_iter()
+            .filter(|sample| *sample != 0.0)
+            .collect()
+    }
+
+    #[test]
+    fn passthrough_is_recorded_and_ducked() {
+        let input = DecodedSound::from_samples(2, SAMPLE_RATE, vec![0.5; 2 * SAMPLE_RATE as usize]);
+        let backend = Arc::new(RecordingBackend::new(vec!["out".to_string()], Some(input)));
+        let mut engine = AudioEngine::new(backend.clone());
+        engine.sync_devices(&settings_with_mic("out"));
+
+        let samples = played(&backend);
+        assert!(!samples.is_empty());
+        assert!(samples.iter().all(|sample| (sample - 0.5).abs() < 1e-4));
+
+        //20dB down with an instant attack
+        engine.set_ducked(true);
+        let samples = played(&backend);
+        assert!(!samples.is_empty());
+        assert!(samples.iter().all(|sample| (sample - 0.05).abs() < 1e-4));
+
+        engine.set_ducked(false);
+        let samples = played(&backend);
+        assert!(!samples.is_empty());
+        assert!(samples.iter().all(|sample| (sample - 0.5).abs() < 1e-4));
+    }
+
+    // the recording backend with an input that can be unplugged, counting how often one was asked for
+    struct SwitchableInput {
+        outputs: RecordingBackend,
+        plugged: Arc<AtomicBool>,
+        input_opened: AtomicUsize,
+    }
+
+    impl SwitchableInput {
+        fn new(plugged: bool) -> Self {
+            Self {
+                outputs: RecordingBackend::new(vec!["out".to_string()], None),
+                plugged: Arc::new(AtomicBool::new(plugged)),
+                input_opened: AtomicUsize::new(0),
+            }
+        }
+    }
+
+    impl AudioBackend for SwitchableInput {
+        fn output_device_names(&self) -> Vec<String> {
+            self.outputs.output_device_names()
+        }
+
+        fn input_device_names(&self) -> Vec<String> {
+            vec![]
+        }
+
+        fn open_output(&self, dev_name: &str, mixer: Arc<Mixer>) -> Option<Box<dyn Output>> {
+            self.outputs.open_output(dev_name, mixer)
+        }
+
+        fn open_input(&self, _dev_name: &str) -> Option<Box<dyn InputSource>> {
+            self.input_opened.fetch_add(1, Ordering::Relaxed);
+            if !self.plugged.load(Ordering::Relaxed) {
+                return None;
+            }
+            Some(Box::new(ConstantInput {
+                plugged: self.plugged.clone(),
+            }))
+        }
+    }
+
+    // records 0.5 on every sample until it gets unplugged
+    struct ConstantInput {
+        plugged: Arc<AtomicBool>,
+    }
+
+    impl InputSource for ConstantInput {
+        fn channels(&self) -> u16 {
+            2
+        }
+
+        fn sample_rate(&self) -> u32 {
+            SAMPLE_RATE
+        }
+
+        fn read(&mut self, buffer: &mut VecDeque<f32>) -> bool {
+            buffer.extend([0.5; 512].iter());
+            self.plugged.load(Ordering::Relaxed)
+        }
+    }
+
+    #[test]
+    fn missing_input_is_not_retried_on_every_press() {
+        let backend = Arc::new(SwitchableInput::new(false));
+        let mut engine = AudioEngine::new(backend.clone());
+        let settings = settings_with_mic("out");
+        engine.sync_devices(&settings);
+        assert_eq!(backend.input_opened.load(Ordering::Relaxed), 1);
+
+        for _ in 0..5 {
+            assert!(engine.outputs(&settings).is_some());
+        }
+        assert_eq!(backend.input_opened.load(Ordering::Relaxed), 1);
+
+        engine.check_devices(&["out".to_string()], &settings);
+        assert_eq!(backend.input_opened.load(Ordering::Relaxed), 2);
+        engine.sync_devices(&settings);
+        assert_eq!(backend.input_opened.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn passthrough_comes_back_with_its_input() {
+        let backend = Arc::new(SwitchableInput::new(true));
+        let mut engine = AudioEngine::new(backend.clone());
+        let settings = settings_with_mic("out");
+        engine.sync_devices(&settings);
+        assert!(heard(&backend.outputs, "out"));
+
+        //the input ends once it notices it is gone, presses do not bring it back
+        backend.plugged.store(false, Ordering::Relaxed);
+        heard(&backend.outputs, "out");
+        assert!(!heard(&backend.outputs, "out"));
+        assert!(engine.outputs(&settings).is_some());
+        engine.check_devices(&["out".to_string()], &settings);
+        assert!(!heard(&backend.outputs, "out"));
+
+        backend.plugged.store(true, Ordering::Relaxed);
+        engine.check_devices(&["out".to_string()], &settings);
+        assert!(heard(&backend.outputs, "out"));
+    }
+
+    fn settings_with_output(dev_name: &str, policy: DeviceLossPolicy) -> AudioSettings {
+        let mut settings = AudioSettings::default();
+        settings.outputs = vec![OutputBus {
+            dev_name: dev_name.to_string(),
+            volume: 100,
+            muted: false,
+        }];
+        settings.device_loss = policy;
+        settings
+    }
+
+    // whether something was played on the device since the last call
+    fn heard(backend: &RecordingBackend, dev_name: &str) -> bool {
+        backend.advance(64);
+        backend.take_recorded(dev_name).iter().any(|sample| *sample != 0.0)
+    }
+
+    fn constant_source() -> BoxedSource {
+        Box::new(DecodedSound::from_samples(2, SAMPLE_RATE, vec![0.5; 2 * SAMPLE_RATE as usize]).source(None, None))
+    }
+
+    #[test]
+    fn unlisted_open_device_is_lost_after_a_few_checks() {
+        let backend = Arc::new(RecordingBackend::new(vec!["default".to_string(), "a".to_string()], None));
+        let mut engine = AudioEngine::new(backend.clone());
+        let settings = settings_with_output("a", DeviceLossPolicy::Pause);
+        engine.sync_devices(&settings);
+
+        //the stream reports nothing, the device is only gone from the list
+        let without_a = vec!["default".to_string()];
+        for _ in 1..MISSED_CHECKS_UNTIL_LOST {
+            assert!(!engine.check_devices(&without_a, &settings));
+            assert_eq!(engine.output_status(), vec![OutputStatus::Connected]);
+        }
+        assert!(engine.check_devices(&without_a, &settings));
+        assert_eq!(engine.output_status(), vec![OutputStatus::Lost]);
+
+        assert!(engine.check_devices(&backend.output_device_names(), &settings));
+        assert_eq!(engine.output_status(), vec![OutputStatus::Connected]);
+    }
+
+    #[test]
+    fn open_device_back_in_the_list_starts_counting_again() {
+        let backend = Arc::new(RecordingBackend::new(vec!["default".to_string(), "a".to_string()], None));
+        let mut engine = AudioEngine::new(backend.clone());
+        let settings = settings_with_output("a", DeviceLossPolicy::Pause);
+        engine.sync_devices(&settings);
+
+        let without_a = vec!["default".to_string()];
+        for _ in 0..MISSED_CHECKS_UNTIL_LOST * 2 {
+            assert!(!engine.check_devices(&without_a, &settings));
+            assert!(!engine.check_devices(&backend.output_device_names(), &settings));
+        }
+        assert_eq!(engine.output_status(), vec![OutputStatus::Connected]);
+    }
+
+    #[test]
+    fn lost_device_falls_back_and_reconnects() {
+        let backend = Arc::new(RecordingBackend::new(vec!["default".to_string(), "a".to_string()], None));
+        let mut engine = AudioEngine::new(backend.clone());
+        let settings = settings_with_output("a", DeviceLossPolicy::FallBack);
+        let mixer = engine.outputs(&settings).unwrap().remove(0);
+        let _voice = mixer.play(constant_source(), 1.0);
+        assert!(heard(&backend, "a"));
+
+        backend.unplug("a");
+        assert!(engine.check_devices(&backend.output_device_names(), &settings));
+        assert_eq!(engine.output_status(), vec![OutputStatus::FallenBack]);
+        assert!(!engine.is_output_lost());
+        assert!(heard(&backend, "default"));
+
+        backend.plug("a");
+        assert!(engine.check_devices(&backend.output_device_names(), &settings));
+        assert_eq!(engine.output_status(), vec![OutputStatus::Connected]);
+        assert!(heard(&backend, "a"));
+        assert!(!heard(&backend, "default"));
+    }
+
+    #[test]
+    fn lost_device_pauses_until_it_is_back() {
+        let backend = Arc::new(RecordingBackend::new(vec!["default".to_string(), "a".to_string()], None));
+        let mut engine = AudioEngine::new(backend.clone());
+        let settings = settings_with_output("a", DeviceLossPolicy::Pause);
+        engine.sync_devices(&settings);
+
+        backend.unplug("a");
+        assert!(engine.check_devices(&backend.output_device_names(), &settings));
+        assert_eq!(engine.output_status(), vec![OutputStatus::Lost]);
+        assert!(engine.is_output_lost());
+        assert!(engine.outputs(&settings).is_none());
+
+        //nothing changes while it stays away
+        assert!(!engine.check_devices(&backend.output_device_names(), &settings));
+
+        backend.plug("a");
+        assert!(engine.check_devices(&backend.output_device_names(), &settings));
+        assert_eq!(engine.output_status(), vec![OutputStatus::Connected]);
+        assert!(!engine.is_output_lost());
+    }
+}
@@ -1,99 +1,249 @@
+use crate::audio_engine::{AudioEngine, OutputStatus};
+use crate::backend::AudioBackend;
 use crate::sound_player::PlayerMessage;
 use crate::{Message, WindowSettings};
 use iced::{
-    button, slider, Align, Button, Column, Element, HorizontalAlignment, Length, Row, Text,
-    pick_list,
+    button, slider, Align, Button, Checkbox, Column, Command, Element, HorizontalAlignment, Length,
+    Row, Text, pick_list,
 };
 use std::ops::RangeInclusive;
-use std::thread;
 use std::sync::mpsc::Sender;
 use std::sync::{Arc, Mutex};
-use rodio::{DeviceTrait};
-use rodio::cpal::traits::HostTrait;
 use serde::{Serialize, Deserialize};
 
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub(crate) enum AudioType {
-    Output1,
-    Output2,
+    // index into the output buses
+    Output(usize),
+    Mic,
+}
+
+// a route every sound is played on, like headphones, a virtual microphone or a capture device
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct OutputBus {
+    // empty for the default device
+    pub(crate) dev_name: String,
+    // from 0 to 100
+    pub(crate) volume: i32,
+    pub(crate) muted: bool,
+}
+
+// the two fixed outputs of boards saved before there could be any number of them
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+struct LegacyOutputs {
+    output1_slider_value: Option<i32>,
+    output1_muted: Option<bool>,
+    out1_dev_name: Option<String>,
+    output2_slider_value: Option<i32>,
+    output2_muted: Option<bool>,
+    out2_dev_name: Option<String>,
+}
+
+// an output bus as a choice in a pick list, shown by its number
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct BusChoice(usize);
+
+impl std::fmt::Display for BusChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "on output {}", self.0 + 1)
+    }
+}
+
+// what happens to playback while the device selected for an output is missing
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default, Serialize, Deserialize)]
+pub(crate) enum DeviceLossPolicy {
+    // play the output on the default device until the selected one is back
+    #[default]
+    FallBack,
+    // pause every sound until the selected device is back
+    Pause,
+}
+
+impl DeviceLossPolicy {
+    pub(crate) const ALL: [DeviceLossPolicy; 2] = [DeviceLossPolicy::FallBack, DeviceLossPolicy::Pause];
+}
+
+impl std::fmt::Display for DeviceLossPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            DeviceLossPolicy::FallBack => "play on the default device",
+            DeviceLossPolicy::Pause => "pause until it is back",
+        };
+        write!(f, "{}", name)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub(crate) enum AudioSettingsMessage {
     SliderChange(i32, AudioType),
     MutePressed(AudioType),
-    OutDev1Selected(String),
-    OutDev2Selected(String),
+    OutDevSelected(usize, String),
+    AddOutput,
+    RemoveOutput(usize),
+    MicDevSelected(String),
+    MicOutputSelected(usize),
+    DeviceLossSelected(DeviceLossPolicy),
+    DuckingToggled(bool),
+    DuckDepthChange(i32),
+    DuckAttackChange(i32),
+    DuckReleaseChange(i32),
+    NormalizeToggled(bool),
+    TargetLoudnessChange(i32),
 }
 
 #[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub(crate) struct AudioSettings {
-    pub(crate) output2_slider_value: i32,
-    pub(crate) output2_muted: bool,
-    pub(crate) output1_slider_value: i32,
-    pub(crate) output1_muted: bool,
-    pub(crate) out2_dev_name: String,
-    pub(crate) out1_dev_name: String,
+    // every sound plays on all of them, there is always at least one
+    pub(crate) outputs: Vec<OutputBus>,
+    #[serde(flatten, skip_serializing)]
+    legacy_outputs: LegacyOutputs,
+    pub(crate) device_loss: DeviceLossPolicy,
+    // input device that is played on the output at mic_output while the passthrough is unmuted
+    pub(crate) mic_dev_name: String,
+    pub(crate) mic_output: usize,
+    pub(crate) mic_volume: i32,
+    pub(crate) mic_muted: bool,
+    // turn the microphone passthrough down while sounds are playing
+    pub(crate) ducking: bool,
+    // in dB
+    pub(crate) duck_depth: i32,
+    // how fast the passthrough gets turned down and back up, in milliseconds
+    pub(crate) duck_attack: i32,
+    pub(crate) duck_release: i32,
+    // adjust the gain of every sound so they all reach the target loudness
+    pub(crate) normalize_loudness: bool,
+    // in LUFS
+    pub(crate) target_loudness: i32,
 }
 
 impl Default for AudioSettings {
     fn default() -> Self {
         //TODO save and load Settings
         Self {
-            output2_slider_value: 0,
-            output2_muted: false,
-            output1_slider_value: 0,
-            output1_muted: false,
-            out2_dev_name: "".to_string(),
-            out1_dev_name: "".to_string()
+            outputs: vec![OutputBus::default(), OutputBus::default()],
+            legacy_outputs: Default::default(),
+            device_loss: DeviceLossPolicy::FallBack,
+            mic_dev_name: "".to_string(),
+            mic_output: 1,
+            mic_volume: 100,
+            mic_muted: true,
+            ducking: false,
+            duck_depth: 12,
+            duck_attack: 50,
+            duck_release: 500,
+            normalize_loudness: true,
+            target_loudness: -18,
+        }
+    }
+}
+
+impl AudioSettings {
+    // moves the outputs of a board saved before there could be any number of them to the output buses
+    // and makes sure there is at least one
+    pub(crate) fn migrate(&mut self) {
+        let legacy = std::mem::take(&mut self.legacy_outputs);
+        if legacy.output1_slider_value.is_some() || legacy.out1_dev_name.is_some() {
+            self.outputs = vec![
+                OutputBus {
+                    dev_name: legacy.out1_dev_name.unwrap_or_default(),
+                    volume: legacy.output1_slider_value.unwrap_or_default(),
+                    muted: legacy.output1_muted.unwrap_or_default(),
+                },
+                OutputBus {
+                    dev_name: legacy.out2_dev_name.unwrap_or_default(),
+                    volume: legacy.output2_slider_value.unwrap_or_default(),
+                    muted: legacy.output2_muted.unwrap_or_default(),
+                },
+            ];
+        }
+        if self.outputs.is_empty() {
+            self.outputs.push(OutputBus::default());
+        }
+    }
+
+    // removes the output bus at index unless it is the last one left, returns whether it was removed
+    // the passthrough stays on the output it plays on, it gets muted if that output is the one removed
+    pub(crate) fn remove_output(&mut self, index: usize) -> bool {
+        if self.outputs.len() <= 1 || index >= self.outputs.len() {
+            return false;
+        }
+        let mic_output = self.mic_output();
+        self.outputs.remove(index);
+        if index == mic_output {
+            //the microphone must not end up on whichever output takes the place of the removed one
+            self.mic_muted = true;
+        } else if index < mic_output {
+            self.mic_output = mic_output - 1;
         }
+        true
+    }
+
+    // the output the microphone passthrough plays on, the last one if the chosen one is gone
+    pub(crate) fn mic_output(&self) -> usize {
+        self.mic_output.min(self.outputs.len().saturating_sub(1))
     }
 }
 
+// the widget states of the controls of one output bus
+#[derive(Default)]
+struct BusControls {
+    mute_button: button::State,
+    slider: slider::State,
+    list_state: pick_list::State<String>,
+    remove_button: button::State,
+}
+
 pub(crate) struct AudioSettingsModel {
     pub(crate) audio_settings: Arc<Mutex<AudioSettings>>,
     pub(crate) video_settings: Arc<Mutex<WindowSettings>>,
-    output2_slider: slider::State,
-    output2_mute_button: button::State,
-    output1_slider: slider::State,
-    output1_mute_button: button::State,
-    out1_list_state: pick_list::State<String>,
-    out2_list_state: pick_list::State<String>,
+    pub(crate) audio_engine: Arc<Mutex<AudioEngine>>,
+    // the engine's backend, kept here to list devices without locking the engine
+    backend: Arc<dyn AudioBackend>,
+    // one for every output bus
+    bus_controls: Vec<BusControls>,
+    add_output_button: button::State,
+    mic_slider: slider::State,
+    mic_mute_button: button::State,
+    mic_list_state: pick_list::State<String>,
+    mic_output_list_state: pick_list::State<BusChoice>,
+    device_loss_list_state: pick_list::State<DeviceLossPolicy>,
+    // of every output bus as of the last device check
+    output_status: Vec<OutputStatus>,
+    duck_depth_slider: slider::State,
+    duck_attack_slider: slider::State,
+    duck_release_slider: slider::State,
+    target_loudness_slider: slider::State,
     out_dev_names: Vec<String>,
-    out2_dev_name: String,
-    out1_dev_name: String,
-}
-
-fn get_audio_device_names() -> Vec<String>{
-    let handle = thread::spawn(|| -> Vec<String>{
-            let mut out_names = vec![];
-            if let Ok(devs) = rodio::cpal::default_host().output_devices() {
-                for dev in devs {
-                    if let Ok(name) = dev.name() {
-                        out_names.push(name)
-                    }
-                }
-            }
-        out_names
-    });
-    handle.join().unwrap()
+    in_dev_names: Vec<String>,
 }
 
 impl Default for AudioSettingsModel{
     fn default() -> Self {
+        let audio_engine = AudioEngine::default();
+        let backend = audio_engine.backend();
         Self {
             audio_settings: Arc::new(Mutex::new(Default::default())),
             video_settings: Arc::new(Mutex::new(Default::default())),
-            output2_slider: Default::default(),
-            output2_mute_button: Default::default(),
-            output1_slider: Default::default(),
-            output1_mute_button: Default::default(),
-            out1_list_state: Default::default(),
-            out2_list_state: Default::default(),
-            out_dev_names: get_audio_device_names(),
-            out2_dev_name: "".to_string(),
-            out1_dev_name: "".to_string()
+            audio_engine: Arc::new(Mutex::new(audio_engine)),
+            bus_controls: vec![],
+            add_output_button: Default::default(),
+            mic_slider: Default::default(),
+            mic_mute_button: Default::default(),
+            mic_list_state: Default::default(),
+            mic_output_list_state: Default::default(),
+            device_loss_list_state: Default::default(),
+            output_status: vec![],
+            duck_depth_slider: Default::default(),
+            duck_attack_slider: Default::default(),
+            duck_release_slider: Default::default(),
+            target_loudness_slider: Default::default(),
+            out_dev_names: backend.output_device_names(),
+            in_dev_names: backend.input_device_names(),
+            backend,
         }
     }
 }
@@ -109,10 +259,103 @@ impl AudioSettingsModel {
         let pick_list_width = (width/100) * 20;
         let padding: u16 = 5;
         let spacing: u16 = 10;
+        let status_texts = self.output_status_texts();
         let settings = self.audio_settings.lock().unwrap();
+        //every output bus needs its own widget states
+        self.bus_controls.resize_with(settings.outputs.len(), Default::default);
+        let output_count = settings.outputs.len();
+        let out_dev_names = &self.out_dev_names;
+        let mic_output_choices: Vec<BusChoice> = (0..output_count).map(BusChoice).collect();
         Column::new()
-            .padding(padding as u16)
-            //add output1 controls
+            .padding(padding)
+            //add a row of controls for every output bus
+            .push(
+                self.bus_controls
+                    .iter_mut()
+                    .zip(settings.outputs.iter())
+                    .enumerate()
+                    .fold(Column::new(), |column, (index, (controls, bus))| {
+                        let row = Row::new()
+                            .spacing(spacing)
+                            .padding(padding)
+                            .align_items(Align::Start)
+                            .push(
+                                Button::new(
+                                    &mut controls.mute_button,
+                                    if bus.muted {
+                                        Text::new(format!("unmute output {}", index + 1))
+                                            .horizontal_alignment(HorizontalAlignment::Center)
+                                    } else {
+                                        Text::new(format!("mute output {}", index + 1))
+                                            .horizontal_alignment(HorizontalAlignment::Center)
+                                    },
+                                )
+                                    .on_press(Message::AudioSettings(AudioSettingsMessage::MutePressed(
+                                        AudioType::Output(index),
+                                    )))
+                                    .width(Length::from(mute_width as u16)),
+                            )
+                            .push(
+                                slider::Slider::new(
+                                    &mut controls.slider,
+                                    RangeInclusive::new(0, 100),
+                                    bus.volume,
+                                    Self::slider_change(AudioType::Output(index)),
+                                )
+                                .step(1)
+                                .width(Length::from(slider_width as u16)),
+                            )
+                            .push(Text::new(bus.volume.to_string()))
+                            .push(
+                                iced::widget::PickList::new(
+                                    &mut controls.list_state,
+                                    &out_dev_names[..],
+                                    Some(bus.dev_name.clone()),
+                                    move |name| {
+                                        Message::AudioSettings(AudioSettingsMessage::OutDevSelected(index, name))
+                                    },
+                                )
+                                    .width(Length::from(pick_list_width as u16))
+                            );
+                        //there is always at least one output
+                        let row = if output_count > 1 {
+                            row.push(
+                                Button::new(&mut controls.remove_button, Text::new("remove"))
+                                    .on_press(Message::AudioSettings(AudioSettingsMessage::RemoveOutput(index))),
+                            )
+                        } else {
+                            row
+                        };
+                        column.push(row)
+                    })
+            )
+            .push(
+                Row::new().padding(padding).push(
+                    Button::new(&mut self.add_output_button, Text::new("add output"))
+                        .on_press(Message::AudioSettings(AudioSettingsMessage::AddOutput)),
+                )
+            )
+            //add device loss controls and what happened to missing devices
+            .push(
+                status_texts.into_iter().fold(
+                    Row::new()
+                        .spacing(spacing)
+                        .padding(padding)
+                        .align_items(Align::Center)
+                        .push(Text::new("if an output device goes missing:"))
+                        .push(
+                            iced::widget::PickList::new(
+                                &mut self.device_loss_list_state,
+                                &DeviceLossPolicy::ALL[..],
+                                Some(settings.device_loss),
+                                |policy| Message::AudioSettings(AudioSettingsMessage::DeviceLossSelected(policy)),
+                            )
+                            .width(Length::from(pick_list_width as u16)),
+                        ),
+                    |row, text| row.push(Text::new(text)),
+                )
+            )
+            //add microphone passthrough controls
             .push(
                 Row::new()
                     .spacing(spacing)
@@ -120,83 +363,120 @@ impl AudioSettingsModel {
                     .align_items(Align::Start)
                     .push(
                         Button::new(
-                            &mut self.output1_mute_button,
-                            if settings.output1_muted {
-                                Text::new("unmute output 1")
+                            &mut self.mic_mute_button,
+                            if settings.mic_muted {
+                                Text::new("unmute microphone")
                                     .horizontal_alignment(HorizontalAlignment::Center)
                             } else {
-                                Text::new("mute output 1")
+                                Text::new("mute microphone")
                                     .horizontal_alignment(HorizontalAlignment::Center)
                             },
                         )
                             .on_press(Message::AudioSettings(AudioSettingsMessage::MutePressed(
-                                AudioType::Output1,
+                                AudioType::Mic,
                             )))
-                            .width(Length::from(mute_width as u16)),
+                            .width(Length::from(mute_width as u16))
                     )
                     .push(
                         slider::Slider::new(
-                            &mut self.output1_slider,
+                            &mut self.mic_slider,
                             RangeInclusive::new(0, 100),
-                            settings.output1_slider_value,
-                            Self::slider_change(AudioType::Output1),
+                            settings.mic_volume,
+                            Self::slider_change(AudioType::Mic),
                         )
                         .step(1)
                         .width(Length::from(slider_width as u16)),
                     )
-                    .push(Text::new(settings.output1_slider_value.to_string()))
+                    .push(Text::new(settings.mic_volume.to_string()))
                     .push(
                         iced::widget::PickList::new(
-                            &mut self.out1_list_state,
-                            &self.out_dev_names,
-                            Some(self.out1_dev_name.clone()),
-                            Message::AudioSettingsOutDev1Selected
+                            &mut self.mic_list_state,
+                            &self.in_dev_names,
+                            Some(settings.mic_dev_name.clone()),
+                            |name| Message::AudioSettings(AudioSettingsMessage::MicDevSelected(name)),
                         )
                             .width(Length::from(pick_list_width as u16))
                     )
+                    .push(
+                        iced::widget::PickList::new(
+                            &mut self.mic_output_list_state,
+                            mic_output_choices,
+                            Some(BusChoice(settings.mic_output())),
+                            |choice: BusChoice| {
+                                Message::AudioSettings(AudioSettingsMessage::MicOutputSelected(choice.0))
+                            },
+                        )
+                    )
             )
-            //add output2 controls
+            //add ducking controls
             .push(
                 Row::new()
                     .spacing(spacing)
                     .padding(padding)
-                    .align_items(Align::Start)
+                    .align_items(Align::Center)
                     .push(
-                        Button::new(
-                            &mut self.output2_mute_button,
-                            if settings.output2_muted {
-                                Text::new("unmute output 2")
-                                    .horizontal_alignment(HorizontalAlignment::Center)
-                            } else {
-                                Text::new("mute output 2")
-                                    .horizontal_alignment(HorizontalAlignment::Center)
-                            },
-                        )
-                            .on_press(Message::AudioSettings(AudioSettingsMessage::MutePressed(
-                                AudioType::Output2,
-                            )))
-                            .width(Length::from(mute_width as u16))
+                        Checkbox::new(settings.ducking, "duck microphone while sounds play", |val| {
+                            Message::AudioSettings(AudioSettingsMessage::DuckingToggled(val))
+                        })
+                        .width(Length::from(mute_width as u16)),
                     )
                     .push(
                         slider::Slider::new(
-                            &mut self.output2_slider,
-                            RangeInclusive::new(0, 100),
-                            settings.output2_slider_value,
-                            Self::slider_change(AudioType::Output2),
+                            &mut self.duck_depth_slider,
+                            RangeInclusive::new(0, 40),
+                            settings.duck_depth,
+                            |val| Message::AudioSettings(AudioSettingsMessage::DuckDepthChange(val)),
                         )
                         .step(1)
-                        .width(Length::from(slider_width as u16)),
+                        .width(Length::from((slider_width / 3) as u16)),
                     )
-                    .push(Text::new(settings.output2_slider_value.to_string()))
+                    .push(Text::new(format!("-{} dB", settings.duck_depth)))
                     .push(
-                        iced::widget::PickList::new(
-                            &mut self.out2_list_state,
-                            &self.out_dev_names,
-                            Some(self.out2_dev_name.clone()),
-                            Message::AudioSettingsOutDev2Selected,
+                        slider::Slider::new(
+                            &mut self.duck_attack_slider,
+                            RangeInclusive::new(0, 1000),
+                            settings.duck_attack,
+                            |val| Message::AudioSettings(AudioSettingsMessage::DuckAttackChange(val)),
                         )
-                            .width(Length::from(pick_list_width as u16))
+                        .step(10)
+                        .width(Length::from((slider_width / 3) as u16)),
                     )
+                    .push(Text::new(format!("attack {} ms", settings.duck_attack)))
+                    .push(
+                        slider::Slider::new(
+                            &mut self.duck_release_slider,
+                            RangeInclusive::new(0, 3000),
+                            settings.duck_release,
+                            |val| Message::AudioSettings(AudioSettingsMessage::DuckReleaseChange(val)),
+                        )
+                        .step(10)
+                        .width(Length::from((slider_width / 3) as u16)),
+                    )
+                    .push(Text::new(format!("release {} ms", settings.duck_release)))
+            )
+            //add loudness normalization controls
+            .push(
+                Row::new()
+                    .spacing(spacing)
+                    .padding(padding)
+                    .align_items(Align::Center)
+                    .push(
+                        Checkbox::new(settings.normalize_loudness, "normalize loudness", |val| {
+                            Message::AudioSettings(AudioSettingsMessage::NormalizeToggled(val))
+                        })
+                        .width(Length::from(mute_width as u16)),
+                    )
+                    .push(
+                        slider::Slider::new(
+                            &mut self.target_loudness_slider,
+                            RangeInclusive::new(-40, -6),
+                            settings.target_loudness,
+                            |val| Message::AudioSettings(AudioSettingsMessage::TargetLoudnessChange(val)),
+                        )
+                        .step(1)
+                        .width(Length::from(slider_width as u16)),
+                    )
+                    .push(Text::new(format!("{} LUFS", settings.target_loudness)))
             )
             .into()
     }
@@ -207,49 +487,202 @@ impl AudioSettingsModel {
         player_update_channels: Vec<Sender<PlayerMessage>>,
     ) {
         let mut settings = self.audio_settings.lock().unwrap();
+        //the engine has to apply device changes and everything about the microphone passthrough
+        let mut engine_changed = false;
 
         //change settings
         match msg {
             AudioSettingsMessage::SliderChange(val, audio_type) => match audio_type {
-                AudioType::Output1 => settings.output1_slider_value = val,
-                AudioType::Output2 => settings.output2_slider_value = val,
+                AudioType::Output(index) => {
+                    if let Some(bus) = settings.outputs.get_mut(index) {
+                        bus.volume = val;
+                    }
+                }
+                AudioType::Mic => {
+                    settings.mic_volume = val;
+                    engine_changed = true;
+                }
             }
 
             AudioSettingsMessage::MutePressed(audio_type) => match audio_type {
-                AudioType::Output1 => settings.output1_muted = !settings.output1_muted,
-                AudioType::Output2 => settings.output2_muted = !settings.output2_muted,
+                AudioType::Output(index) => {
+                    if let Some(bus) = settings.outputs.get_mut(index) {
+                        bus.muted = !bus.muted;
+                    }
+                }
+                AudioType::Mic => {
+                    settings.mic_muted = !settings.mic_muted;
+                    engine_changed = true;
+                }
+            }
+
+            AudioSettingsMessage::DuckingToggled(val) => {
+                settings.ducking = val;
+                engine_changed = true;
+            }
+
+            AudioSettingsMessage::DuckDepthChange(val) => {
+                settings.duck_depth = val;
+                engine_changed = true;
+            }
+
+            AudioSettingsMessage::DuckAttackChange(val) => {
+                settings.duck_attack = val;
+                engine_changed = true;
             }
 
-            AudioSettingsMessage::OutDev1Selected(name) => {
-                self.out_dev_names = get_audio_device_names();
-                self.out1_dev_name = name.clone();
-                settings.out1_dev_name = name;
+            AudioSettingsMessage::DuckReleaseChange(val) => {
+                settings.duck_release = val;
+                engine_changed = true;
             }
 
+            AudioSettingsMessage::NormalizeToggled(val) => settings.normalize_loudness = val,
+
+            AudioSettingsMessage::TargetLoudnessChange(val) => settings.target_loudness = val,
 
-            AudioSettingsMessage::OutDev2Selected(name) => {
-                self.out_dev_names = get_audio_device_names();
-                self.out2_dev_name = name.clone();
-                settings.out2_dev_name = name;
+            AudioSettingsMessage::OutDevSelected(index, name) => {
+                self.out_dev_names = self.backend.output_device_names();
+                if let Some(bus) = settings.outputs.get_mut(index) {
+                    bus.dev_name = name;
+                }
+                engine_changed = true;
+            }
+
+            AudioSettingsMessage::AddOutput => {
+                settings.outputs.push(OutputBus::default());
+                engine_changed = true;
+            }
+
+            AudioSettingsMessage::RemoveOutput(index) => {
+                if settings.remove_output(index) {
+                    self.audio_engine.lock().unwrap().remove_output(index);
+                    engine_changed = true;
+                }
+            }
+
+            AudioSettingsMessage::MicOutputSelected(index) => {
+                settings.mic_output = index;
+                engine_changed = true;
+            }
+
+            AudioSettingsMessage::DeviceLossSelected(policy) => {
+                settings.device_loss = policy;
+                engine_changed = true;
+            }
+
+            AudioSettingsMessage::MicDevSelected(name) => {
+                self.in_dev_names = self.backend.input_device_names();
+                settings.mic_dev_name = name;
+                engine_changed = true;
             }
         }
 
+        //open the newly selected device now instead of on the next button press
+        if engine_changed {
+            let current_settings = settings.clone();
+            drop(settings);
+            let mut engine = self.audio_engine.lock().unwrap();
+            engine.sync_devices(&current_settings);
+            self.output_status = engine.output_status();
+        }
 
         //send settings changed message to players
         for chan in player_update_channels.iter() {
-            chan.send(PlayerMessage::SettingsChange);
+            let _ = chan.send(PlayerMessage::SettingsChange);
         }
     }
 
+    // lists the output devices in the background, the names arrive as Message::OutputDevicesListed
+    pub(crate) fn list_devices(&self) -> Command<Message> {
+        let backend = self.backend.clone();
+        Command::perform(async move { backend.output_device_names() }, Message::OutputDevicesListed)
+    }
+
+    // closes outputs whose device went away and reconnects the ones whose device is back
+    // returns whether playback has to wait for a missing device
+    pub(crate) fn devices_listed(&mut self, available: Vec<String>) -> bool {
+        let current_settings = self.audio_settings.lock().unwrap().clone();
+        let mut engine = self.audio_engine.lock().unwrap();
+        engine.check_devices(&available, &current_settings);
+        self.output_status = engine.output_status();
+        self.out_dev_names = available;
+        engine.is_output_lost()
+    }
+
+    fn output_status_texts(&self) -> Vec<String> {
+        self.output_status
+            .iter()
+            .enumerate()
+            .map(|(index, status)| (index + 1, status))
+            .filter_map(|(number, status)| match status {
+                OutputStatus::Connected => None,
+                OutputStatus::FallenBack => {
+                    Some(format!("output {}: device missing, playing on the default device", number))
+                }
+                OutputStatus::Lost => {
+                    Some(format!("output {}: no device, playback paused until it is back", number))
+                }
+            })
+            .collect()
+    }
+
     //function builder that returns an onChanged function depending on the audio_type
-    fn slider_change(audio_type: AudioType) -> fn(i32) -> Message {
-        return match audio_type {
-            AudioType::Output1 => |val: i32| {
-                Message::AudioSettings(AudioSettingsMessage::SliderChange(val, AudioType::Output1))
-            },
-            AudioType::Output2 => |val: i32| {
-                Message::AudioSettings(AudioSettingsMessage::SliderChange(val, AudioType::Output2))
-            },
-        };
+    fn slider_change(audio_type: AudioType) -> impl Fn(i32) -> Message {
+        move |val: i32| Message::AudioSettings(AudioSettingsMessage::SliderChange(val, audio_type))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings_with_outputs(count: usize, mic_output: usize) -> AudioSettings {
+        AudioSettings {
+            outputs: (0..count)
+                .map(|index| OutputBus {
+                    dev_name: format!("output {}", index),
+                    ..Default::default()
+                })
+                .collect(),
+            mic_output,
+            mic_muted: false,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn removing_an_output_before_the_mic_output_keeps_the_mic_on_it() {
+        let mut settings = settings_with_outputs(3, 2);
+        assert!(settings.remove_output(0));
+        assert_eq!(settings.outputs[settings.mic_output()].dev_name, "output 2");
+        assert!(!settings.mic_muted);
+    }
+
+    #[test]
+    fn removing_the_mic_output_mutes_the_mic() {
+        let mut settings = settings_with_outputs(3, 1);
+        assert!(settings.remove_output(1));
+        assert!(settings.mic_muted);
+    }
+
+    #[test]
+    fn the_last_output_is_never_removed() {
+        let mut settings = settings_with_outputs(1, 0);
+        assert!(!settings.remove_output(0));
+        assert!(!settings.remove_output(3));
+        assert_eq!(settings.outputs.len(), 1);
+    }
+
+    #[test]
+    fn boards_with_two_fixed_outputs_get_two_buses() {
+        let saved = "output1_slider_value: 70\nout1_dev_name: headphones\noutput2_muted: true\nout2_dev_name: cable\n";
+        let mut settings: AudioSettings = serde_yaml::from_str(saved).unwrap();
+        settings.migrate();
+        assert_eq!(settings.outputs.len(), 2);
+        assert_eq!(settings.outputs[0].dev_name, "headphones");
+        assert_eq!(settings.outputs[0].volume, 70);
+        assert_eq!(settings.outputs[1].dev_name, "cable");
+        assert!(settings.outputs[1].muted);
+        assert!(!serde_yaml::to_string(&settings).unwrap().contains("output1"));
     }
 }
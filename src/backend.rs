@@ -0,0 +1,220 @@
+use crate::mic::{DeviceInput, FileInput, InputSource};
+use crate::mixer::{Mixer, MixerSource};
+use crate::recording::RecordingBackend;
+use crate::sample_cache::DecodedSound;
+use rodio::cpal::traits::{HostTrait, StreamTrait};
+use rodio::cpal::{
+    BuildStreamError, Device, OutputCallbackInfo, Sample, SampleFormat, Stream, StreamConfig,
+};
+use rodio::source::UniformSourceIterator;
+use rodio::DeviceTrait;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread;
+
+// set to "null" to play into memory instead of the sound cards, nothing is heard then
+const BACKEND_VAR: &str = "OXIDIZED_SOUNDBOARD_BACKEND";
+// with the null backend, set to a directory to save what every output played to a WAV file in it
+const RECORD_DIR_VAR: &str = "OXIDIZED_SOUNDBOARD_RECORD_DIR";
+// set to the path of a sound file to pass it through in a loop instead of recording a device
+const FAKE_INPUT_VAR: &str = "OXIDIZED_SOUNDBOARD_FAKE_MIC";
+
+// lists, opens and records the devices sounds are played on
+pub(crate) trait AudioBackend: Send + Sync {
+    fn output_device_names(&self) -> Vec<String>;
+
+    fn input_device_names(&self) -> Vec<String>;
+
+    // plays the mixer on the output device with the given name, or on the default device for an empty name
+    // None if there is no such device
+    fn open_output(&self, dev_name: &str, mixer: Arc<Mixer>) -> Option<Box<dyn Output>>;
+
+    // opens the input device with the given name, falls back to the default device if no device has that name
+    fn open_input(&self, dev_name: &str) -> Option<Box<dyn InputSource>>;
+}
+
+// an output device playing a mixer until it is dropped
+pub(crate) trait Output: Send {
+    // whether the device went away while it was open
+    fn is_lost(&self) -> bool;
+}
+
+// the backend chosen by OXIDIZED_SOUNDBOARD_BACKEND, the sound cards if it is not set
+// with OXIDIZED_SOUNDBOARD_FAKE_MIC set, its file replaces every input device
+pub(crate) fn from_env() -> Arc<dyn AudioBackend> {
+    let fake_input = std::env::var(FAKE_INPUT_VAR)
+        .ok()
+        .and_then(|path| DecodedSound::decode(&path).ok());
+    match std::env::var(BACKEND_VAR) {
+        Ok(name) if name == "null" => {
+            let backend = Arc::new(RecordingBackend::new(
+                vec!["null output 1".to_string(), "null output 2".to_string()],
+                fake_input,
+            ));
+            let record_dir = std::env::var(RECORD_DIR_VAR).ok().map(PathBuf::from);
+            RecordingBackend::run_in_real_time(&backend, record_dir);
+            backend
+        }
+        _ => Arc::new(CpalBackend { fake_input }),
+    }
+}
+
+// the sound cards of the default cpal host
+pub(crate) struct CpalBackend {
+    // played in a loop instead of recording an input device if set
+    fake_input: Option<DecodedSound>,
+}
+
+impl AudioBackend for CpalBackend {
+    fn output_device_names(&self) -> Vec<String> {
+        let handle = thread::spawn(|| -> Vec<String> {
+            let mut out_names = vec![];
+            if let Ok(devs) = rodio::cpal::default_host().output_devices() {
+                for dev in devs {
+                    if let Ok(name) = dev.name() {
+                        out_names.push(name)
+                    }
+                }
+            }
+            out_names
+        });
+        handle.join().unwrap_or_default()
+    }
+
+    fn input_device_names(&self) -> Vec<String> {
+        let handle = thread::spawn(|| -> Vec<String> {
+            let mut in_names = vec![];
+            if let Ok(devs) = rodio::cpal::default_host().input_devices() {
+                for dev in devs {
+                    if let Ok(name) = dev.name() {
+                        in_names.push(name)
+                    }
+                }
+            }
+            in_names
+        });
+        handle.join().unwrap_or_default()
+    }
+
+    fn open_output(&self, dev_name: &str, mixer: Arc<Mixer>) -> Option<Box<dyn Output>> {
+        Some(Box::new(CpalOutput::open(dev_name.to_string(), mixer)?))
+    }
+
+    fn open_input(&self, dev_name: &str) -> Option<Box<dyn InputSource>> {
+        match &self.fake_input {
+            Some(sound) => Some(Box::new(FileInput::new(sound.clone()))),
+            None => Some(Box::new(DeviceInput::open(dev_name.to_string())?)),
+        }
+    }
+}
+
+// an output device of the sound card, the stream stays open until it is dropped
+struct CpalOutput {
+    stream: StreamHandle,
+}
+
+impl CpalOutput {
+    fn open(dev_name: String, mixer: Arc<Mixer>) -> Option<Self> {
+        let ((), stream) = spawn_stream(move |lost| {
+            let host = rodio::cpal::default_host();
+            let dev = if dev_name.is_empty() {
+                host.default_output_device()
+            } else {
+                host.output_devices()
+                    .ok()
+                    .and_then(|mut devs| devs.find(|dev| dev.name().ok() == Some(dev_name.clone())))
+            }?;
+
+            let supported = dev.default_output_config().ok()?;
+            let format = supported.sample_format();
+            let config: StreamConfig = supported.into();
+            let stream = match format {
+                SampleFormat::F32 => build_stream::<f32>(&dev, &config, mixer, lost),
+                SampleFormat::I16 => build_stream::<i16>(&dev, &config, mixer, lost),
+                SampleFormat::U16 => build_stream::<u16>(&dev, &config, mixer, lost),
+            };
+            let stream = stream.ok()?;
+            stream.play().ok()?;
+            Some((stream, ()))
+        })?;
+        Some(Self { stream })
+    }
+}
+
+// a cpal stream running on its own thread, cpal streams can not be moved between threads
+// the stream is closed once the handle is dropped
+pub(crate) struct StreamHandle {
+    // set by the stream's error callback
+    lost: Arc<AtomicBool>,
+    // dropping this sender ends the thread holding the stream
+    _close: Sender<()>,
+}
+
+impl StreamHandle {
+    // whether the stream reported that its device went away
+    pub(crate) fn is_lost(&self) -> bool {
+        self.lost.load(Ordering::Relaxed)
+    }
+}
+
+// builds a stream with open on its own thread, open gets the flag the stream's error callback should set
+// None if open could not build the stream
+pub(crate) fn spawn_stream<T, F>(open: F) -> Option<(T, StreamHandle)>
+where
+    T: Send + 'static,
+    F: FnOnce(Arc<AtomicBool>) -> Option<(Stream, T)> + Send + 'static,
+{
+    let (opened_tx, opened_rx) = mpsc::channel();
+    let (close_tx, close_rx) = mpsc::channel::<()>();
+    let lost = Arc::new(AtomicBool::new(false));
+
+    let stream_lost = lost.clone();
+    thread::spawn(move || match open(stream_lost) {
+        Some((_stream, info)) => {
+            let _ = opened_tx.send(Some(info));
+            //keep the stream alive until the handle gets dropped
+            let _ = close_rx.recv();
+        }
+        None => {
+            let _ = opened_tx.send(None);
+        }
+    });
+
+    let info = opened_rx.recv().ok()??;
+    Some((
+        info,
+        StreamHandle {
+            lost,
+            _close: close_tx,
+        },
+    ))
+}
+
+// a stream that converts what the mixer mixes to the format of the device
+fn build_stream<T: Sample>(
+    dev: &Device,
+    config: &StreamConfig,
+    mixer: Arc<Mixer>,
+    lost: Arc<AtomicBool>,
+) -> Result<Stream, BuildStreamError> {
+    let mut source = UniformSourceIterator::new(MixerSource::new(mixer), config.channels, config.sample_rate.0);
+    dev.build_output_stream(
+        config,
+        move |data: &mut [T], _: &OutputCallbackInfo| {
+            for sample in data.iter_mut() {
+                *sample = T::from(&source.next().unwrap_or(0.0));
+            }
+        },
+        //only wasapi reports DeviceNotAvailable, alsa reports an unplugged device as a backend error
+        move |_| lost.store(true, Ordering::Relaxed),
+    )
+}
+
+impl Output for CpalOutput {
+    fn is_lost(&self) -> bool {
+        self.stream.is_lost()
+    }
+}
@@ -0,0 +1,446 @@
+use crate::biquad::Biquad;
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+use std::time::Duration;
+
+// echoes and reverb tails never ring out longer than this after the sound ended
+const MAX_TAIL: f64 = 10.0;
+// delay lengths of the reverb in samples at 44.1kHz, taken from freeverb
+const COMB_TUNINGS: [usize; 4] = [1116, 1188, 1277, 1356];
+const ALLPASS_TUNINGS: [usize; 2] = [556, 441];
+// the channels of the reverb get slightly different delays so it sounds wide
+const STEREO_SPREAD: usize = 23;
+
+// an effect applied to a sound, saved with its button
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum Effect {
+    // repeats of the sound every delay seconds, each one feedback times as loud as the one before
+    Echo { delay: f64, feedback: f64, mix: f64 },
+    // room from 0 (small) to 1 (large)
+    Reverb { room: f64, mix: f64 },
+    // cutoff frequencies in Hz
+    LowPass { cutoff: f64 },
+    HighPass { cutoff: f64 },
+    // keeps only some bits of every sample and repeats every sample downsample times
+    Bitcrush { bits: f64, downsample: f64 },
+    // soft clipping, drive 1 is barely audible
+    Distortion { drive: f64 },
+}
+
+impl Effect {
+    // the same effect with every parameter limited to values that work
+    fn clamped(self) -> Self {
+        match self {
+            Effect::Echo { delay, feedback, mix } => Effect::Echo {
+                delay: clamp(delay, 0.01, 2.0),
+                feedback: clamp(feedback, 0.0, 0.95),
+                mix: clamp(mix, 0.0, 1.0),
+            },
+            Effect::Reverb { room, mix } => Effect::Reverb {
+                room: clamp(room, 0.0, 1.0),
+                mix: clamp(mix, 0.0, 1.0),
+            },
+            Effect::LowPass { cutoff } => Effect::LowPass {
+                cutoff: clamp(cutoff, 20.0, 20000.0),
+            },
+            Effect::HighPass { cutoff } => Effect::HighPass {
+                cutoff: clamp(cutoff, 20.0, 20000.0),
+            },
+            Effect::Bitcrush { bits, downsample } => Effect::Bitcrush {
+                bits: clamp(bits, 1.0, 24.0),
+                downsample: clamp(downsample, 1.0, 64.0),
+            },
+            Effect::Distortion { drive } => Effect::Distortion {
+                drive: clamp(drive, 1.0, 100.0),
+            },
+        }
+    }
+}
+
+// limits a parameter to the given range, a parameter that is not a number becomes the minimum
+fn clamp(value: f64, min: f64, max: f64) -> f64 {
+    if value.is_nan() {
+        min
+    } else {
+        value.clamp(min, max)
+    }
+}
+
+// the kinds of effects, used to pick one in the add view
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EffectKind {
+    Echo,
+    Reverb,
+    LowPass,
+    HighPass,
+    Bitcrush,
+    Distortion,
+}
+
+impl EffectKind {
+    pub(crate) const ALL: [EffectKind; 6] = [
+        EffectKind::Echo,
+        EffectKind::Reverb,
+        EffectKind::LowPass,
+        EffectKind::HighPass,
+        EffectKind::Bitcrush,
+        EffectKind::Distortion,
+    ];
+
+    // names and default values of the parameters of the effect
+    pub(crate) fn params(&self) -> &'static [(&'static str, f64)] {
+        match self {
+            EffectKind::Echo => &[("delay (seconds)", 0.3), ("feedback (0 to 1)", 0.4), ("mix (0 to 1)", 0.5)],
+            EffectKind::Reverb => &[("room (0 to 1)", 0.5), ("mix (0 to 1)", 0.3)],
+            EffectKind::LowPass => &[("cutoff (Hz)", 1000.0)],
+            EffectKind::HighPass => &[("cutoff (Hz)", 500.0)],
+            EffectKind::Bitcrush => &[("bits", 8.0), ("downsample", 4.0)],
+            EffectKind::Distortion => &[("drive", 5.0)],
+        }
+    }
+
+    // the effect with the given parameters, missing ones get their default value
+    pub(crate) fn effect(&self, values: &[Option<f64>]) -> Effect {
+        let value = |index: usize| {
+            values
+                .get(index)
+                .copied()
+                .flatten()
+                .unwrap_or(self.params()[index].1)
+        };
+        match self {
+            EffectKind::Echo => Effect::Echo {
+                delay: value(0),
+                feedback: value(1),
+                mix: value(2),
+            },
+            EffectKind::Reverb => Effect::Reverb {
+                room: value(0),
+                mix: value(1),
+            },
+            EffectKind::LowPass => Effect::LowPass { cutoff: value(0) },
+            EffectKind::HighPass => Effect::HighPass { cutoff: value(0) },
+            EffectKind::Bitcrush => Effect::Bitcrush {
+                bits: value(0),
+                downsample: value(1),
+            },
+            EffectKind::Distortion => Effect::Distortion { drive: value(0) },
+        }
+    }
+}
+
+impl std::fmt::Display for EffectKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            EffectKind::Echo => "echo",
+            EffectKind::Reverb => "reverb",
+            EffectKind::LowPass => "low-pass",
+            EffectKind::HighPass => "high-pass",
+            EffectKind::Bitcrush => "bitcrush",
+            EffectKind::Distortion => "distortion",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// delays samples by a fixed number of samples
+struct DelayLine {
+    buffer: Vec<f32>,
+    pos: usize,
+}
+
+impl DelayLine {
+    fn new(len: usize) -> Self {
+        Self {
+            buffer: vec![0.0; len.max(1)],
+            pos: 0,
+        }
+    }
+
+    // the sample written len samples ago
+    fn read(&self) -> f32 {
+        self.buffer[self.pos]
+    }
+
+    fn write(&mut self, sample: f32) {
+        self.buffer[self.pos] = sample;
+        self.pos = (self.pos + 1) % self.buffer.len();
+    }
+}
+
+// a feedback comb filter with a low-pass in its loop, the building block of the reverb
+struct Comb {
+    line: DelayLine,
+    feedback: f32,
+    damping: f32,
+    filtered: f32,
+}
+
+impl Comb {
+    fn process(&mut self, input: f32) -> f32 {
+        let out = self.line.read();
+        self.filtered = out * (1.0 - self.damping) + self.filtered * self.damping;
+        self.line.write(input + self.filtered * self.feedback);
+        out
+    }
+}
+
+// an allpass filter, smears the echoes of the combs into a dense tail
+struct Allpass {
+    line: DelayLine,
+}
+
+impl Allpass {
+    fn process(&mut self, input: f32) -> f32 {
+        let delayed = self.line.read();
+        self.line.write(input + delayed * 0.5);
+        delayed - input
+    }
+}
+
+// the state of an effect for every channel
+enum Processor {
+    Echo {
+        lines: Vec<DelayLine>,
+        feedback: f32,
+        mix: f32,
+    },
+    Reverb {
+        combs: Vec<Vec<Comb>>,
+        allpasses: Vec<Vec<Allpass>>,
+        mix: f32,
+    },
+    Filter(Vec<Biquad>),
+    Bitcrush {
+        levels: f32,
+        downsample: usize,
+        held: Vec<f32>,
+        frame: usize,
+    },
+    Distortion {
+        drive: f32,
+        norm: f32,
+    },
+}
+
+impl Processor {
+    fn new(effect: Effect, channels: usize, sample_rate: f64) -> Self {
+        match effect.clamped() {
+            Effect::Echo { delay, feedback, mix } => Processor::Echo {
+                lines: (0..channels)
+                    .map(|_| DelayLine::new((delay * sample_rate) as usize))
+                    .collect(),
+                feedback: feedback as f32,
+                mix: mix as f32,
+            },
+            Effect::Reverb { room, mix } => {
+                let scale = sample_rate / 44100.0;
+                let delay = |tuning: usize, channel: usize| {
+                    ((tuning + channel * STEREO_SPREAD) as f64 * scale) as usize
+                };
+                Processor::Reverb {
+                    combs: (0..channels)
+                        .map(|channel| {
+                            COMB_TUNINGS
+                                .iter()
+                                .map(|tuning| Comb {
+                                    line: DelayLine::new(delay(*tuning, channel)),
+                                    feedback: (0.7 + 0.28 * room) as f32,
+                                    damping: 0.2,
+                                    filtered: 0.0,
+                                })
+                                .collect()
+                        })
+                        .collect(),
+                    allpasses: (0..channels)
+                        .map(|channel| {
+                            ALLPASS_TUNINGS
+                                .iter()
+                                .map(|tuning| Allpass {
+                                    line: DelayLine::new(delay(*tuning, channel)),
+                                })
+                                .collect()
+                        })
+                        .collect(),
+                    mix: mix as f32,
+                }
+            }
+            Effect::LowPass { cutoff } => {
+                let (cos, alpha) = filter_terms(cutoff, sample_rate);
+                let filter = Biquad::new(
+                    [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0],
+                    [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+                );
+                Processor::Filter(vec![filter; channels])
+            }
+            Effect::HighPass { cutoff } => {
+                let (cos, alpha) = filter_terms(cutoff, sample_rate);
+                let filter = Biquad::new(
+                    [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+                    [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+                );
+                Processor::Filter(vec![filter; channels])
+            }
+            Effect::Bitcrush { bits, downsample } => Processor::Bitcrush {
+                levels: 2f32.powf(bits as f32 - 1.0),
+                downsample: downsample as usize,
+                held: vec![0.0; channels],
+                frame: 0,
+            },
+            Effect::Distortion { drive } => Processor::Distortion {
+                drive: drive as f32,
+                norm: (drive as f32).tanh(),
+            },
+        }
+    }
+
+    // how long the effect keeps sounding after its input ended, in seconds
+    fn tail(effect: Effect) -> f64 {
+        match effect.clamped() {
+            Effect::Echo { delay, feedback, .. } if feedback > 0.0 => {
+                //until the echoes are 60dB quieter
+                (delay * (0.001f64.ln() / feedback.ln()).ceil()).min(MAX_TAIL)
+            }
+            Effect::Echo { delay, .. } => delay,
+            Effect::Reverb { room, .. } => 1.0 + 4.0 * room,
+            _ => 0.0,
+        }
+    }
+
+    fn process(&mut self, input: f32, channel: usize) -> f32 {
+        match self {
+            Processor::Echo { lines, feedback, mix } => {
+                let line = &mut lines[channel];
+                let delayed = line.read();
+                line.write(input + delayed * *feedback);
+                input + delayed * *mix
+            }
+            Processor::Reverb { combs, allpasses, mix } => {
+                let mut wet = combs[channel]
+                    .iter_mut()
+                    .map(|comb| comb.process(input * 0.05))
+                    .sum::<f32>();
+                for allpass in allpasses[channel].iter_mut() {
+                    wet = allpass.process(wet);
+                }
+                input * (1.0 - *mix) + wet * *mix
+            }
+            Processor::Filter(filters) => filters[channel].process(input as f64) as f32,
+            Processor::Bitcrush {
+                levels,
+                downsample,
+                held,
+                frame,
+            } => {
+                if *frame % *downsample == 0 {
+                    held[channel] = (input * *levels).round() / *levels;
+                }
+                if channel + 1 == held.len() {
+                    *frame += 1;
+                }
+                held[channel]
+            }
+            Processor::Distortion { drive, norm } => (input * *drive).tanh() / *norm,
+        }
+    }
+}
+
+// cosine of the cutoff and the bandwidth term of the audio EQ cookbook filters, with a Q of 1/sqrt(2)
+fn filter_terms(cutoff: f64, sample_rate: f64) -> (f64, f64) {
+    let w0 = 2.0 * PI * cutoff.min(sample_rate * 0.45) / sample_rate;
+    (w0.cos(), w0.sin() / (2.0 * std::f64::consts::FRAC_1_SQRT_2))
+}
+
+// applies an effect to a source and lets it ring out after the source ended
+pub(crate) struct Effected<S> {
+    inner: S,
+    processor: Processor,
+    channels: usize,
+    channel: usize,
+    // whether the source ended and the effect is ringing out
+    ended: bool,
+    // samples of silence still fed to the effect once the source ended
+    tail_samples: usize,
+    tail: Duration,
+}
+
+impl<S: Source<Item = f32>> Effected<S> {
+    pub(crate) fn new(inner: S, effect: Effect) -> Self {
+        let channels = inner.channels().max(1) as usize;
+        let sample_rate = inner.sample_rate() as f64;
+        let tail = Processor::tail(effect);
+
+        Self {
+            processor: Processor::new(effect, channels, sample_rate),
+            channels,
+            channel: 0,
+            ended: false,
+            tail_samples: (tail * sample_rate) as usize * channels,
+            tail: Duration::from_secs_f64(tail),
+            inner,
+        }
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for Effected<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let input = match self.inner.next() {
+            Some(sample) => sample,
+            None if self.tail_samples > 0 => {
+                self.ended = true;
+                self.tail_samples -= 1;
+                0.0
+            }
+            None => return None,
+        };
+
+        let out = self.processor.process(input, self.channel);
+        self.channel = (self.channel + 1) % self.channels;
+        Some(out)
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Effected<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        if self.ended {
+            Some(self.tail_samples)
+        } else {
+            self.inner.current_frame_len()
+        }
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration().map(|duration| duration + self.tail)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_cache::DecodedSound;
+
+    #[test]
+    fn parameters_that_are_not_numbers_still_play() {
+        for kind in EffectKind::ALL.iter() {
+            let values = vec![Some(f64::NAN); kind.params().len()];
+            let sound = DecodedSound::from_samples(2, 44100, vec![0.5; 882]);
+            let effected = Effected::new(sound.source(None, None), kind.effect(&values));
+            assert!(effected.total_duration().is_some(), "{}", kind);
+            for sample in effected {
+                assert!(sample.is_finite(), "{}", kind);
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use rodio::Source;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// lets the player fade out the envelopes of all outputs of a sound at once
+#[derive(Clone, Default)]
+pub(crate) struct StopHandle(Arc<AtomicBool>);
+
+impl StopHandle {
+    pub(crate) fn stop(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// applies a gain envelope to a source: a fade in at the start, a fade out before the end
+// and a fade out once the stop handle is triggered, after which the source ends
+pub(crate) struct Envelope<S> {
+    inner: S,
+    channels: usize,
+    fade_in_frames: usize,
+    fade_out_frames: usize,
+    stop_frames: usize,
+    // frames until the end of the source, None if it is endless
+    total_frames: Option<usize>,
+    played_samples: usize,
+    stop: StopHandle,
+    // frame at which the stop fade began
+    stopped_at: Option<usize>,
+}
+
+impl<S: Source<Item = f32>> Envelope<S> {
+    pub(crate) fn new(
+        inner: S,
+        fade_in: Duration,
+        fade_out: Duration,
+        stop_fade: Duration,
+        stop: StopHandle,
+    ) -> Self {
+        let sample_rate = inner.sample_rate() as f64;
+        let to_frames = |dur: Duration| (dur.as_secs_f64() * sample_rate) as usize;
+        let total_frames = inner.total_duration().map(to_frames);
+
+        Self {
+            channels: inner.channels().max(1) as usize,
+            fade_in_frames: to_frames(fade_in),
+            fade_out_frames: to_frames(fade_out),
+            stop_frames: to_frames(stop_fade),
+            total_frames,
+            played_samples: 0,
+            stop,
+            stopped_at: None,
+            inner,
+        }
+    }
+
+    fn gain(&self, frame: usize) -> f32 {
+        let mut gain: f32 = 1.0;
+
+        if frame < self.fade_in_frames {
+            gain = gain.min(frame as f32 / self.fade_in_frames as f32);
+        }
+
+        if let Some(total_frames) = self.total_frames {
+            let remaining = total_frames.saturating_sub(frame);
+            if remaining < self.fade_out_frames {
+                gain = gain.min(remaining as f32 / self.fade_out_frames as f32);
+            }
+        }
+
+        if let Some(stopped_at) = self.stopped_at {
+            let since_stop = frame - stopped_at;
+            gain = gain.min(1.0 - since_stop as f32 / self.stop_frames as f32);
+        }
+
+        gain
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for Envelope<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let frame = self.played_samples / self.channels;
+
+        //only check for a stop at frame boundaries so all channels end together
+        if self.played_samples.is_multiple_of(self.channels) {
+            if self.stopped_at.is_none() && self.stop.is_stopped() {
+                self.stopped_at = Some(frame);
+            }
+            if let Some(stopped_at) = self.stopped_at {
+                if frame - stopped_at >= self.stop_frames {
+                    return None;
+                }
+            }
+        }
+
+        let sample = self.inner.next()?;
+        self.played_samples += 1;
+        Some(sample * self.gain(frame))
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Envelope<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
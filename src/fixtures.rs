@@ -0,0 +1,53 @@
+use hound::{SampleFormat, WavSpec, WavWriter};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// numbers the files so tests running at the same time never share one
+static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+
+// a file in the temp directory for a test, it is deleted once dropped
+pub(crate) struct TempFile {
+    pub(crate) path: PathBuf,
+}
+
+impl TempFile {
+    pub(crate) fn new(extension: &str) -> Self {
+        let name = format!(
+            "oxidized-soundboard-test-{}-{}.{}",
+            std::process::id(),
+            NEXT_FILE.fetch_add(1, Ordering::Relaxed),
+            extension
+        );
+        Self {
+            path: std::env::temp_dir().join(name),
+        }
+    }
+
+    // a 32 bit float WAV file with the given interleaved samples
+    // rodio 0.14 decodes every WAV file to 16 bit samples, so they come back only to about 1/32768
+    pub(crate) fn wav(channels: u16, sample_rate: u32, samples: &[f32]) -> Self {
+        let file = Self::new("wav");
+        let spec = WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        let mut writer = WavWriter::create(&file.path, spec).unwrap();
+        for sample in samples {
+            writer.write_sample(*sample).unwrap();
+        }
+        writer.finalize().unwrap();
+        file
+    }
+
+    pub(crate) fn path_string(&self) -> String {
+        self.path.to_string_lossy().into_owned()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,116 @@
+use iced_native::event::{self, Event};
+use iced_native::layout;
+use iced_native::mouse;
+use iced_native::overlay;
+use iced_native::{Clipboard, Element, Hasher, Layout, Length, Point, Rectangle, Widget};
+
+// remembers whether the mouse went down on a Hold so its release is reported even outside of it
+#[derive(Debug, Default)]
+pub(crate) struct State {
+    held: bool,
+}
+
+// wraps a widget and reports when the left mouse button goes down on it and when it comes up again
+// a button only reports the release, this is needed for sounds that play while the mouse is held
+pub(crate) struct Hold<'a, Message, Renderer> {
+    state: &'a mut State,
+    content: Element<'a, Message, Renderer>,
+    on_hold: Message,
+    on_release: Message,
+}
+
+impl<'a, Message, Renderer> Hold<'a, Message, Renderer> {
+    pub(crate) fn new<E>(state: &'a mut State, content: E, on_hold: Message, on_release: Message) -> Self
+    where
+        E: Into<Element<'a, Message, Renderer>>,
+    {
+        Self {
+            state,
+            content: content.into(),
+            on_hold,
+            on_release,
+        }
+    }
+}
+
+impl<'a, Message, Renderer> Widget<Message, Renderer> for Hold<'a, Message, Renderer>
+where
+    Message: Clone,
+    Renderer: iced_native::Renderer,
+{
+    fn width(&self) -> Length {
+        self.content.width()
+    }
+
+    fn height(&self) -> Length {
+        self.content.height()
+    }
+
+    fn layout(&self, renderer: &Renderer, limits: &layout::Limits) -> layout::Node {
+        self.content.layout(renderer, limits)
+    }
+
+    fn draw(
+        &self,
+        renderer: &mut Renderer,
+        defaults: &Renderer::Defaults,
+        layout: Layout<'_>,
+        cursor_position: Point,
+        viewport: &Rectangle,
+    ) -> Renderer::Output {
+        self.content
+            .draw(renderer, defaults, layout, cursor_position, viewport)
+    }
+
+    fn hash_layout(&self, state: &mut Hasher) {
+        self.content.hash_layout(state);
+    }
+
+    fn on_event(
+        &mut self,
+        event: Event,
+        layout: Layout<'_>,
+        cursor_position: Point,
+        renderer: &Renderer,
+        clipboard: &mut dyn Clipboard,
+        messages: &mut Vec<Message>,
+    ) -> event::Status {
+        match event {
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left))
+                if layout.bounds().contains(cursor_position) =>
+            {
+                self.state.held = true;
+                messages.push(self.on_hold.clone());
+            }
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) if self.state.held => {
+                self.state.held = false;
+                messages.push(self.on_release.clone());
+            }
+            _ => {}
+        }
+
+        //the wrapped widget still gets every event, so it keeps its own pressed look
+        self.content.on_event(
+            event,
+            layout,
+            cursor_position,
+            renderer,
+            clipboard,
+            messages,
+        )
+    }
+
+    fn overlay(&mut self, layout: Layout<'_>) -> Option<overlay::Element<'_, Message, Renderer>> {
+        self.content.overlay(layout)
+    }
+}
+
+impl<'a, Message, Renderer> From<Hold<'a, Message, Renderer>> for Element<'a, Message, Renderer>
+where
+    Message: 'a + Clone,
+    Renderer: 'a + iced_native::Renderer,
+{
+    fn from(hold: Hold<'a, Message, Renderer>) -> Self {
+        Element::new(hold)
+    }
+}
@@ -0,0 +1,179 @@
+use crate::biquad::Biquad;
+use crate::sample_cache::DecodedSound;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+// quietest loudness and peak that get reported, also used for silent files
+const FLOOR_DB: f64 = -70.0;
+// the normalization gain never pushes the peak above this
+const PEAK_CEILING_DB: f64 = -1.0;
+// the normalization gain never boosts or cuts more than this
+const MAX_GAIN_DB: f64 = 20.0;
+
+// loudness of a sound file as measured when it was added
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub(crate) struct Loudness {
+    // integrated loudness in LUFS
+    pub(crate) integrated: f64,
+    // sample peak in dBFS
+    pub(crate) peak: f64,
+}
+
+impl Loudness {
+    // linear gain that brings the sound to the target loudness without clipping its peak
+    pub(crate) fn gain_to(&self, target_lufs: f64) -> f32 {
+        let gain_db = (target_lufs - self.integrated)
+            .clamp(-MAX_GAIN_DB, MAX_GAIN_DB)
+            .min(PEAK_CEILING_DB - self.peak);
+        10f64.powf(gain_db / 20.0) as f32
+    }
+}
+
+// the two stage K-weighting filter of ITU-R BS.1770 for the given sample rate
+fn k_weighting(sample_rate: f64) -> [Biquad; 2] {
+    //high shelf modelling the acoustic effect of the head
+    let f0 = 1681.974450955533;
+    let gain_db = 3.999843853973347;
+    let q = 0.7071752369554196;
+    let k = (PI * f0 / sample_rate).tan();
+    let vh = 10f64.powf(gain_db / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let shelf = Biquad::new(
+        [vh + vb * k / q + k * k, 2.0 * (k * k - vh), vh - vb * k / q + k * k],
+        [1.0 + k / q + k * k, 2.0 * (k * k - 1.0), 1.0 - k / q + k * k],
+    );
+
+    //high pass removing the lowest frequencies
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+    let k = (PI * f0 / sample_rate).tan();
+    let high_pass = Biquad::new(
+        [1.0, -2.0, 1.0],
+        [1.0 + k / q + k * k, 2.0 * (k * k - 1.0), 1.0 - k / q + k * k],
+    );
+
+    [shelf, high_pass]
+}
+
+fn to_lufs(mean_square: f64) -> f64 {
+    if mean_square > 0.0 {
+        -0.691 + 10.0 * mean_square.log10()
+    } else {
+        f64::NEG_INFINITY
+    }
+}
+
+// measures the gated integrated loudness (EBU R128) and the sample peak of a sound
+// all channels are weighted equally, surround weighting is not applied
+pub(crate) fn analyze(sound: &DecodedSound) -> Loudness {
+    let channels = sound.channels().max(1) as usize;
+    let sample_rate = sound.sample_rate() as f64;
+    let samples = sound.samples();
+
+    //sum of the squared K-weighted samples of every 100ms segment
+    let segment_frames = ((sample_rate / 10.0) as usize).max(1);
+    let mut filters = vec![k_weighting(sample_rate); channels];
+    let mut segments = vec![];
+    let mut segment_sum = 0.0;
+    let mut peak: f32 = 0.0;
+
+    for (frame_index, frame) in samples.chunks(channels).enumerate() {
+        for (sample, filter) in frame.iter().zip(filters.iter_mut()) {
+            peak = peak.max(sample.abs());
+            let [shelf, high_pass] = filter;
+            let weighted = high_pass.process(shelf.process(*sample as f64));
+            segment_sum += weighted * weighted;
+        }
+        if (frame_index + 1) % segment_frames == 0 {
+            segments.push(segment_sum);
+            segment_sum = 0.0;
+        }
+    }
+
+    //400ms blocks overlapping by 75%, short sounds are measured as a single block
+    let block_mean_squares: Vec<f64> = if segments.len() >= 4 {
+        segments
+            .windows(4)
+            .map(|block| block.iter().sum::<f64>() / (4 * segment_frames) as f64)
+            .collect()
+    } else {
+        let frames = (samples.len() / channels).max(1);
+        vec![(segments.iter().sum::<f64>() + segment_sum) / frames as f64]
+    };
+
+    let mean_above = |threshold: f64| {
+        let gated: Vec<f64> = block_mean_squares
+            .iter()
+            .copied()
+            .filter(|mean_square| to_lufs(*mean_square) > threshold)
+            .collect();
+        if gated.is_empty() {
+            None
+        } else {
+            Some(gated.iter().sum::<f64>() / gated.len() as f64)
+        }
+    };
+
+    //absolute gate at -70 LUFS, then a relative gate 10 LU below the absolute gated loudness
+    let integrated = mean_above(FLOOR_DB)
+        .and_then(|mean_square| mean_above((to_lufs(mean_square) - 10.0).max(FLOOR_DB)))
+        .map_or(FLOOR_DB, to_lufs);
+
+    let peak = if peak > 0.0 {
+        (20.0 * (peak as f64).log10()).max(FLOOR_DB)
+    } else {
+        FLOOR_DB
+    };
+
+    Loudness { integrated, peak }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a stereo sine with the given peak level in dBFS
+    fn stereo_sine(freq: f64, level_db: f64, secs: f64) -> DecodedSound {
+        let sample_rate = 48000;
+        let amplitude = 10f64.powf(level_db / 20.0);
+        let samples = (0..(secs * sample_rate as f64) as usize)
+            .flat_map(|frame| {
+                let sample = (amplitude * (2.0 * PI * freq * frame as f64 / sample_rate as f64).sin()) as f32;
+                [sample, sample]
+            })
+            .collect();
+        DecodedSound::from_samples(2, sample_rate, samples)
+    }
+
+    #[test]
+    fn sine_at_minus_23_dbfs_measures_minus_23_lufs() {
+        let loudness = analyze(&stereo_sine(1000.0, -23.0, 5.0));
+        assert!((loudness.integrated + 23.0).abs() <= 0.1, "{}", loudness.integrated);
+        assert!((loudness.peak + 23.0).abs() <= 0.01, "{}", loudness.peak);
+    }
+
+    #[test]
+    fn silence_measures_the_floor() {
+        let loudness = analyze(&DecodedSound::from_samples(2, 48000, vec![0.0; 96000]));
+        assert_eq!(loudness.integrated, FLOOR_DB);
+        assert_eq!(loudness.peak, FLOOR_DB);
+    }
+
+    #[test]
+    fn gain_keeps_the_peak_below_the_ceiling() {
+        let loudness = Loudness { integrated: -30.0, peak: -3.0 };
+        let gain_db = 20.0 * (loudness.gain_to(-18.0) as f64).log10();
+        assert!((gain_db - (PEAK_CEILING_DB + 3.0)).abs() < 1e-4, "{}", gain_db);
+    }
+
+    #[test]
+    fn gain_is_limited_to_20_db() {
+        let quiet = Loudness { integrated: -60.0, peak: -50.0 };
+        let boost_db = 20.0 * (quiet.gain_to(-18.0) as f64).log10();
+        assert!((boost_db - MAX_GAIN_DB).abs() < 1e-4, "{}", boost_db);
+
+        let loud = Loudness { integrated: 0.0, peak: 0.0 };
+        let cut_db = 20.0 * (loud.gain_to(-40.0) as f64).log10();
+        assert!((cut_db + MAX_GAIN_DB).abs() < 1e-4, "{}", cut_db);
+    }
+}
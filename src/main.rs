@@ -1,26 +1,45 @@
 mod add_view;
+mod audio_engine;
 mod audio_settings;
+mod backend;
+mod biquad;
+mod effects;
+mod envelope;
+#[cfg(test)]
+mod fixtures;
+mod hold;
+mod loudness;
+mod mic;
+mod mixer;
+mod pitch;
+mod recording;
+mod render;
 mod play_buttons;
+mod sample_cache;
 mod sound_player;
+mod ticker;
 
 use crate::add_view::{AddView, AddViewMessage};
 use crate::audio_settings::{AudioSettings, AudioSettingsMessage, AudioSettingsModel};
-use crate::play_buttons::{ButtonMessage, PlayButtons, PlayButton};
-use crate::sound_player::{PlayerMessage, Sound, PlayState};
+use crate::play_buttons::{ButtonMessage, ButtonOptions, PlayButtons, PlayButton};
+use crate::sound_player::{PlayerMessage, Sound};
 use iced::{
     executor, scrollable, Align, Application, Clipboard, Column, Command, Element,
     Scrollable, Settings,
 };
 use iced_native::{Event, Subscription};
-use std::sync::mpsc::{Sender};
 use std::sync::{Arc, Mutex};
 use serde::{Serialize, Deserialize};
 use std::io::{Write, BufReader};
 use std::ops::Deref;
+use std::time::Duration;
 
 use std::collections::HashMap;
 use home::home_dir;
 
+// how often the output devices are checked for having gone missing or come back
+const DEVICE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
 fn main() -> iced::Result {
     if cfg!(target_os = "windows"){
         Example::run(Settings {
@@ -52,18 +71,39 @@ pub(crate) struct Example {
 #[derive(Debug, Clone)]
 pub(crate) enum Message {
     Save,
+    StopAll,
     AudioSettings(AudioSettingsMessage),
     PlayButtons(ButtonMessage),
     AddView(AddViewMessage),
     WindowResized(usize, usize),
-    AudioSettingsOutDev1Selected(String),//not an elegant solution
-    AudioSettingsOutDev2Selected(String), //not an elegant solution
+    Tick,
+    CheckDevices,
+    OutputDevicesListed(Vec<String>),
 }
 
 #[derive(Serialize, Deserialize)]
 struct SaveSettings{
     audio: AudioSettings,
+    //only read to load boards saved before buttons were stored with their settings
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     sound_paths: HashMap<String,String>,
+    #[serde(default)]
+    buttons: Vec<SavedButton>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedButton{
+    name: String,
+    #[serde(flatten)]
+    sound: Sound,
+    #[serde(flatten)]
+    options: ButtonOptions,
+    #[serde(default = "default_volume")]
+    volume: i32,
+}
+
+fn default_volume() -> i32 {
+    100
 }
 
 fn save(settings: &SaveSettings){
@@ -75,7 +115,7 @@ fn save(settings: &SaveSettings){
         if let Some(dir) = dir.to_str() {
             if let Ok(mut file) = std::fs::File::create(String::from(dir) + file_name) {
                 if let Ok(yaml) = serde_yaml::to_string(settings) {
-                    file.write(yaml.as_bytes());
+                    let _ = file.write_all(yaml.as_bytes());
                 }
             }
         }
@@ -88,17 +128,23 @@ fn load_save() -> Option<SaveSettings>{
     #[cfg(target_os = "windows")]
         let file_name = "\\oxidized_soundboard.yaml";
 
-    let yaml = std::fs::File::open(String::from(String::from(home_dir()?.to_str()?)) + file_name).ok()?;
+    let yaml = std::fs::File::open(String::from(home_dir()?.to_str()?) + file_name).ok()?;
     let reader = BufReader::new(yaml);
     let mut settings: SaveSettings = serde_yaml::from_reader(reader).ok()?;
-    let mut temp: HashMap<String, String> = HashMap::new();
-    for (k,v) in settings.sound_paths.iter().filter(
-        |path|
-            crate::add_view::check_filetype(path.1)
-    ){
-          temp.insert(k.clone(),v.clone());
+    for (name, path) in settings.sound_paths.drain(){
+        settings.buttons.push(SavedButton{
+            name,
+            sound: Sound::new(vec![path]),
+            options: Default::default(),
+            volume: default_volume(),
+        });
+    }
+    //files that were moved or deleted since are dropped, and so are buttons without any files left
+    for btn in settings.buttons.iter_mut() {
+        btn.sound.files.retain(|file| crate::add_view::check_filetype(&file.path));
+        btn.sound.layers.retain(|layer| crate::add_view::check_filetype(&layer.file.path));
     }
-    settings.sound_paths = temp;
+    settings.buttons.retain(|btn| !btn.sound.files.is_empty());
     Some(settings)
 }
 
@@ -110,31 +156,40 @@ impl Application for Example {
 
     fn new(_flags: ()) -> (Self, Command<Message>) {
         let mut app = Example::default();
+        let mut commands = vec![];
         //load settings
-        if let Some(settings) = load_save(){
+        if let Some(mut settings) = load_save(){
+            //boards saved with the two fixed outputs get them as output buses
+            settings.audio.migrate();
             app.audio_model.audio_settings = Arc::new(Mutex::new(settings.audio));
-            for (name, path) in settings.sound_paths.iter(){
-                app.play_buttons.buttons.push(PlayButton{
-                    player_handle_sender: None,
-                    player_handle_receiver: None,
-                    play_state: Default::default(),
-                    delete_state: Default::default(),
-                    sound: Sound {
-                        file_path: path.clone(),
-                        state: PlayState::Stopped
-                    },
-                    name: name.clone()
-                })
+            for btn in settings.buttons{
+                //boards saved before loudness normalization still have to be measured
+                for file in btn.sound.all_files() {
+                    if file.loudness.is_none() {
+                        commands.push(app.play_buttons.analyze(file.path.clone()));
+                    } else {
+                        sample_cache::preload(app.play_buttons.sample_cache.clone(), file.path.clone());
+                    }
+                }
+                let mut button = PlayButton::new(btn.sound, btn.name);
+                button.options = btn.options;
+                *button.volume.lock().unwrap() = btn.volume;
+                app.play_buttons.buttons.push(button)
             }
         }
 
+        //open the selected output devices once, players reuse them
+        let current_settings = app.audio_model.audio_settings.lock().unwrap().clone();
+        app.audio_model.audio_engine.lock().unwrap().sync_devices(&current_settings);
+
         //enable memory sharing between components
         app.audio_settings = app.audio_model.audio_settings.clone();
+        app.play_buttons.audio_engine = app.audio_model.audio_engine.clone();
         app.play_buttons.audio_settings = app.audio_settings.clone();
         app.play_buttons.video_settings = app.window_settings.clone();
         app.audio_model.video_settings = app.window_settings.clone();
         app.add_view.video_settings = app.window_settings.clone();
-        (app, Command::none())
+        (app, Command::batch(commands))
     }
 
     fn title(&self) -> String {
@@ -144,64 +199,53 @@ impl Application for Example {
     fn update(&mut self, message: Self::Message, _clipboard: &mut Clipboard) -> Command<Message> {
         match message {
             Message::AudioSettings(msg) => {
-                let mut player_update_channels: Vec<Sender<PlayerMessage>> = vec![];
-
-                for btn in &self.play_buttons.buttons {
-                    if let Some(tx) = btn.player_handle_sender.clone() {
-                        player_update_channels.push(tx);
-                    }
-                }
-
+                let player_update_channels = self.play_buttons.player_channels();
                 AudioSettingsModel::update(&mut self.audio_model, msg, player_update_channels)
             }
 
             Message::AddView(msg) => {
                 let btn_msg = AddView::update(&mut self.add_view, msg);
                 if let Some(msg) = btn_msg {
-                    PlayButtons::update(&mut self.play_buttons, msg);
+                    return PlayButtons::update(&mut self.play_buttons, msg);
                 }
             }
 
-            Message::PlayButtons(msg) => PlayButtons::update(&mut self.play_buttons, msg),
+            Message::PlayButtons(msg) => return PlayButtons::update(&mut self.play_buttons, msg),
 
-            Message::WindowResized(width, height) => {
-                let mut settings = self.window_settings.lock().unwrap();
-                settings.width = width;
-                settings.height = height;
-            }
+            Message::Tick => self.play_buttons.poll_players(),
 
-            Message::AudioSettingsOutDev2Selected(name) => {
-                let mut player_update_channels: Vec<Sender<PlayerMessage>> = vec![];
+            Message::CheckDevices => return self.audio_model.list_devices(),
 
-                for btn in &self.play_buttons.buttons {
-                    if let Some(tx) = btn.player_handle_sender.clone() {
-                        player_update_channels.push(tx);
-                    }
-                }
-                AudioSettingsModel::update(&mut self.audio_model, AudioSettingsMessage::OutDev2Selected(name), player_update_channels);
+            Message::OutputDevicesListed(names) => {
+                let lost = self.audio_model.devices_listed(names);
+                self.play_buttons.set_output_lost(lost);
             }
 
-            Message::AudioSettingsOutDev1Selected(name) => {
-                let mut player_update_channels: Vec<Sender<PlayerMessage>> = vec![];
-
-                for btn in &self.play_buttons.buttons {
-                    if let Some(tx) = btn.player_handle_sender.clone() {
-                        player_update_channels.push(tx);
-                    }
+            Message::StopAll => {
+                for tx in self.play_buttons.player_channels() {
+                    let _ = tx.send(PlayerMessage::Stop);
                 }
-                AudioSettingsModel::update(&mut self.audio_model, AudioSettingsMessage::OutDev1Selected(name), player_update_channels);
+            }
+
+            Message::WindowResized(width, height) => {
+                let mut settings = self.window_settings.lock().unwrap();
+                settings.width = width;
+                settings.height = height;
             }
 
             Message::Save => {
                 //save current settings and buttons
-                let mut sound_paths: HashMap<String, String> = Default::default();
-                for btn in  self.play_buttons.buttons.iter(){
-                sound_paths.insert(btn.name.clone(),btn.sound.file_path.clone());
-                }
+                let buttons = self.play_buttons.buttons.iter().map(|btn| SavedButton{
+                    name: btn.name.clone(),
+                    sound: btn.sound.clone(),
+                    options: btn.options.clone(),
+                    volume: *btn.volume.lock().unwrap(),
+                }).collect();
                 let audio = self.audio_settings.clone().lock().unwrap().deref().clone();
                 save(&SaveSettings{
                 audio ,
-                sound_paths,
+                sound_paths: Default::default(),
+                buttons,
                 });
             }
         }
@@ -210,7 +254,7 @@ impl Application for Example {
     }
 
     fn subscription(&self) -> Subscription<Message> {
-        iced_native::subscription::events_with(|event, _status| match event {
+        let events = iced_native::subscription::events_with(|event, status| match event {
             Event::Window(event) => {
                 match event {
                     iced_native::window::Event::Resized { width, height } => {
@@ -222,8 +266,39 @@ impl Application for Example {
                     _ => None,
                 }
             }
+            Event::Keyboard(iced_native::keyboard::Event::KeyPressed {
+                key_code: iced_native::keyboard::KeyCode::Escape,
+                ..
+            }) => Some(Message::StopAll),
+            //keys typed into a text input do not press buttons
+            Event::Keyboard(iced_native::keyboard::Event::KeyPressed { key_code, .. })
+                if status == iced_native::event::Status::Ignored =>
+            {
+                Some(Message::PlayButtons(ButtonMessage::KeyPressed(key_code)))
+            }
+            //releases always get through, so a gated sound never keeps playing
+            Event::Keyboard(iced_native::keyboard::Event::KeyReleased { key_code, .. }) => {
+                Some(Message::PlayButtons(ButtonMessage::KeyReleased(key_code)))
+            }
+            Event::Keyboard(iced_native::keyboard::Event::ModifiersChanged(modifiers)) => {
+                Some(Message::PlayButtons(ButtonMessage::ModifiersChanged(modifiers)))
+            }
             _ => None,
-        })
+        });
+
+        //missing devices are noticed and reconnected even while nothing plays
+        let device_check = ticker::every(DEVICE_CHECK_INTERVAL).map(|_| Message::CheckDevices);
+
+        //only poll the players while there are any, so an idle board does not redraw constantly
+        if self.play_buttons.has_active_players() {
+            Subscription::batch(vec![
+                events,
+                device_check,
+                ticker::every(Duration::from_millis(50)).map(|_| Message::Tick),
+            ])
+        } else {
+            Subscription::batch(vec![events, device_check])
+        }
     }
 
     fn view(&mut self) -> Element<'_, Self::Message> {
@@ -0,0 +1,325 @@
+use crate::backend::{self, AudioBackend, StreamHandle};
+use crate::mixer::{Mixer, Voice};
+use crate::sample_cache::DecodedSound;
+use rodio::cpal::traits::{HostTrait, StreamTrait};
+use rodio::cpal::{InputCallbackInfo, SampleFormat, StreamConfig};
+use rodio::{DeviceTrait, Source};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// recorded samples older than this are dropped so the passthrough does not lag behind
+const MAX_LATENCY: Duration = Duration::from_millis(100);
+
+// something that records samples, like a microphone
+pub(crate) trait InputSource: Send {
+    fn channels(&self) -> u16;
+
+    fn sample_rate(&self) -> u32;
+
+    // appends the samples recorded since the last call, returns false once the input is gone
+    fn read(&mut self, buffer: &mut VecDeque<f32>) -> bool;
+}
+
+// records an input device through cpal
+pub(crate) struct DeviceInput {
+    channels: u16,
+    sample_rate: u32,
+    samples: Receiver<Vec<f32>>,
+    stream: StreamHandle,
+}
+
+impl DeviceInput {
+    pub(crate) fn open(dev_name: String) -> Option<Self> {
+        let (samples_tx, samples_rx) = mpsc::channel();
+
+        let ((channels, sample_rate), stream) = backend::spawn_stream(move |lost| {
+            let host = rodio::cpal::default_host();
+            let mut device = host.default_input_device();
+
+            if let Ok(devs) = host.input_devices() {
+                for dev in devs {
+                    if let Ok(name) = dev.name() {
+                        if name == dev_name {
+                            device = Some(dev);
+                            break;
+                        }
+                    }
+                }
+            }
+
+            let dev = device?;
+            let supported = dev.default_input_config().ok()?;
+            let format = supported.sample_format();
+            let config: StreamConfig = supported.into();
+            //any error of an input stream means it stopped recording, hosts report unplugging differently
+            let stream = match format {
+                SampleFormat::F32 => {
+                    let tx = samples_tx.clone();
+                    let lost = lost.clone();
+                    dev.build_input_stream(
+                        &config,
+                        move |data: &[f32], _: &InputCallbackInfo| {
+                            let _ = tx.send(data.to_vec());
+                        },
+                        move |_| lost.store(true, Ordering::Relaxed),
+                    )
+                }
+                SampleFormat::I16 => {
+                    let tx = samples_tx.clone();
+                    let lost = lost.clone();
+                    dev.build_input_stream(
+                        &config,
+                        move |data: &[i16], _: &InputCallbackInfo| {
+                            let _ = tx.send(data.iter().map(|s| *s as f32 / 32768.0).collect());
+                        },
+                        move |_| lost.store(true, Ordering::Relaxed),
+                    )
+                }
+                SampleFormat::U16 => {
+                    let tx = samples_tx.clone();
+                    let lost = lost.clone();
+                    dev.build_input_stream(
+                        &config,
+                        move |data: &[u16], _: &InputCallbackInfo| {
+                            let _ = tx.send(
+                                data.iter()
+                                    .map(|s| (*s as f32 - 32768.0) / 32768.0)
+                                    .collect(),
+                            );
+                        },
+                        move |_| lost.store(true, Ordering::Relaxed),
+                    )
+                }
+            };
+            //the callbacks own the senders from now on, so the receiver notices when the stream is gone
+            drop(samples_tx);
+            let stream = stream.ok()?;
+            stream.play().ok()?;
+            Some((stream, (config.channels, config.sample_rate.0)))
+        })?;
+
+        Some(Self {
+            channels,
+            sample_rate,
+            samples: samples_rx,
+            stream,
+        })
+    }
+}
+
+impl InputSource for DeviceInput {
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn read(&mut self, buffer: &mut VecDeque<f32>) -> bool {
+        if self.stream.is_lost() {
+            return false;
+        }
+        loop {
+            match self.samples.try_recv() {
+                Ok(samples) => buffer.extend(samples),
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) => return false,
+            }
+        }
+    }
+}
+
+// plays a sound file in a loop at the pace a device would record it, stands in for a microphone
+pub(crate) struct FileInput {
+    sound: DecodedSound,
+    started: Instant,
+    // samples handed out so far
+    read: usize,
+}
+
+impl FileInput {
+    // starts recording the sound from its beginning
+    pub(crate) fn new(sound: DecodedSound) -> Self {
+        Self {
+            sound,
+            started: Instant::now(),
+            read: 0,
+        }
+    }
+}
+
+impl InputSource for FileInput {
+    fn channels(&self) -> u16 {
+        self.sound.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sound.sample_rate()
+    }
+
+    fn read(&mut self, buffer: &mut VecDeque<f32>) -> bool {
+        let samples_per_sec = self.sound.channels() as f64 * self.sound.sample_rate() as f64;
+        let channels = self.sound.channels().max(1) as usize;
+        //only whole frames, like a device delivers them
+        let due = (self.started.elapsed().as_secs_f64() * samples_per_sec) as usize / channels * channels;
+        let samples = self.sound.samples();
+        buffer.extend((self.read..due).map(|index| samples[index % samples.len()]));
+        self.read = self.read.max(due);
+        true
+    }
+}
+
+// turns the passthrough down while sounds are playing, shared between the engine and the playback
+#[derive(Default)]
+pub(crate) struct Ducking {
+    active: AtomicBool,
+    // the gain while ducked and the attack and release times in seconds, stored as f32 bits
+    gain: AtomicU32,
+    attack: AtomicU32,
+    release: AtomicU32,
+}
+
+impl Ducking {
+    pub(crate) fn set_active(&self, active: bool) {
+        self.active.store(active, Ordering::Relaxed);
+    }
+
+    // depth in dB, attack and release in milliseconds
+    pub(crate) fn configure(&self, depth: i32, attack: i32, release: i32) {
+        let gain = 10f32.powf(-(depth.max(0) as f32) / 20.0);
+        self.gain.store(gain.to_bits(), Ordering::Relaxed);
+        self.attack.store((attack.max(0) as f32 / 1000.0).to_bits(), Ordering::Relaxed);
+        self.release.store((release.max(0) as f32 / 1000.0).to_bits(), Ordering::Relaxed);
+    }
+
+    // the gain the playback moves towards and how many seconds it takes to get most of the way there
+    fn target(&self) -> (f32, f32) {
+        if self.active.load(Ordering::Relaxed) {
+            (
+                f32::from_bits(self.gain.load(Ordering::Relaxed)),
+                f32::from_bits(self.attack.load(Ordering::Relaxed)),
+            )
+        } else {
+            (1.0, f32::from_bits(self.release.load(Ordering::Relaxed)))
+        }
+    }
+}
+
+// plays what an input records, silence while it has not recorded anything yet
+pub(crate) struct InputPlayback {
+    input: Box<dyn InputSource>,
+    ducking: Arc<Ducking>,
+    // gain of the current frame, follows the ducking target smoothly
+    gain: f32,
+    buffer: VecDeque<f32>,
+    channels: usize,
+    // samples left of the frame being played
+    frame_left: usize,
+    // whether the current frame is silence because the input had nothing yet
+    silent: bool,
+    max_buffered: usize,
+}
+
+impl InputPlayback {
+    pub(crate) fn new(input: Box<dyn InputSource>, ducking: Arc<Ducking>) -> Self {
+        let channels = input.channels().max(1) as usize;
+        let max_frames = (MAX_LATENCY.as_secs_f64() * input.sample_rate() as f64) as usize;
+        Self {
+            ducking,
+            gain: 1.0,
+            channels,
+            buffer: VecDeque::new(),
+            frame_left: 0,
+            silent: false,
+            max_buffered: max_frames.max(1) * channels,
+            input,
+        }
+    }
+}
+
+impl Iterator for InputPlayback {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        //decide at every frame whether it gets played or replaced by silence, so channels never mix up
+        if self.frame_left == 0 {
+            if !self.input.read(&mut self.buffer) {
+                return None;
+            }
+            if self.buffer.len() > self.max_buffered {
+                let excess = (self.buffer.len() - self.max_buffered) / self.channels * self.channels;
+                self.buffer.drain(..excess);
+            }
+            self.silent = self.buffer.len() < self.channels;
+            self.frame_left = self.channels;
+
+            //one pole smoothing, after the attack or release time the gain is 63% of the way there
+            let (target, time) = self.ducking.target();
+            let frames = time * self.input.sample_rate() as f32;
+            if frames >= 1.0 {
+                self.gain = target + (self.gain - target) * (-1.0 / frames).exp();
+            } else {
+                self.gain = target;
+            }
+        }
+
+        self.frame_left -= 1;
+        if self.silent {
+            Some(0.0)
+        } else {
+            self.buffer.pop_front().map(|sample| sample * self.gain)
+        }
+    }
+}
+
+impl Source for InputPlayback {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+// an input played continuously on an output
+pub(crate) struct Passthrough {
+    pub(crate) dev_name: String,
+    voice: Voice,
+}
+
+impl Passthrough {
+    pub(crate) fn start(
+        dev_name: String,
+        backend: &dyn AudioBackend,
+        output: &Mixer,
+        ducking: Arc<Ducking>,
+        volume: f32,
+    ) -> Option<Self> {
+        let input = backend.open_input(&dev_name)?;
+        let voice = output.play(Box::new(InputPlayback::new(input, ducking)), volume);
+        Some(Self { dev_name, voice })
+    }
+
+    pub(crate) fn set_volume(&self, volume: f32) {
+        self.voice.set_volume(volume);
+    }
+
+    // whether the input stopped recording, it has to be opened again to pass anything through
+    pub(crate) fn is_done(&self) -> bool {
+        self.voice.is_done()
+    }
+}
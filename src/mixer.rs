@@ -0,0 +1,168 @@
+use rodio::source::UniformSourceIterator;
+use rodio::Source;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+// every mixer mixes to this format, devices convert it to their own
+pub(crate) const CHANNELS: u16 = 2;
+pub(crate) const SAMPLE_RATE: u32 = 44100;
+// frames a device pulls from the mixer at once
+const BLOCK_FRAMES: usize = 256;
+
+pub(crate) type BoxedSource = Box<dyn Source<Item = f32> + Send>;
+type Voices = Vec<(Arc<VoiceControls>, UniformSourceIterator<BoxedSource, f32>)>;
+
+// the voices playing on one output, independent of the device that plays them
+// so the device can be swapped or go missing without the voices losing their place
+#[derive(Default)]
+pub(crate) struct Mixer {
+    voices: Mutex<Voices>,
+    // set once the output got removed, nothing plays on it anymore
+    closed: AtomicBool,
+}
+
+impl Mixer {
+    // starts playing the source at the given volume
+    pub(crate) fn play(&self, source: BoxedSource, volume: f32) -> Voice {
+        let controls = Arc::new(VoiceControls::default());
+        controls.volume.store(volume.to_bits(), Ordering::Relaxed);
+        let mut voices = self.lock_voices();
+        //checked under the lock so a voice can not slip in while the mixer gets closed
+        if self.closed.load(Ordering::Relaxed) {
+            controls.stopped.store(true, Ordering::Relaxed);
+        } else {
+            voices.push((controls.clone(), UniformSourceIterator::new(source, CHANNELS, SAMPLE_RATE)));
+        }
+        Voice { controls }
+    }
+
+    // stops every voice, voices played later are stopped right away
+    pub(crate) fn close(&self) {
+        let mut voices = self.lock_voices();
+        self.closed.store(true, Ordering::Relaxed);
+        for (controls, _) in voices.drain(..) {
+            controls.stopped.store(true, Ordering::Relaxed);
+        }
+    }
+
+    // adds the next frames of every voice that is not paused to out, which holds whole frames
+    pub(crate) fn mix(&self, out: &mut [f32]) {
+        let mut voices = self.lock_voices();
+        voices.retain(|(controls, _)| !controls.stopped.load(Ordering::Relaxed));
+
+        for (controls, source) in voices.iter_mut() {
+            if controls.paused.load(Ordering::Relaxed) {
+                continue;
+            }
+            let volume = f32::from_bits(controls.volume.load(Ordering::Relaxed));
+            for sample in out.iter_mut() {
+                match source.next() {
+                    Some(next) => *sample += next * volume,
+                    None => {
+                        controls.stopped.store(true, Ordering::Relaxed);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    // the audio callbacks lock the voices, a panicking source must not silence the output for good
+    fn lock_voices(&self) -> MutexGuard<'_, Voices> {
+        self.voices.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[derive(Default)]
+struct VoiceControls {
+    // f32 bits
+    volume: AtomicU32,
+    paused: AtomicBool,
+    // set when the voice was stopped or its source ended
+    stopped: AtomicBool,
+}
+
+// a source playing on a mixer, it stops once dropped
+pub(crate) struct Voice {
+    controls: Arc<VoiceControls>,
+}
+
+impl Voice {
+    pub(crate) fn set_volume(&self, volume: f32) {
+        self.controls.volume.store(volume.to_bits(), Ordering::Relaxed);
+    }
+
+    pub(crate) fn pause(&self) {
+        self.controls.paused.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn resume(&self) {
+        self.controls.paused.store(false, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stop(&self) {
+        self.controls.stopped.store(true, Ordering::Relaxed);
+    }
+
+    // whether the source has ended or the voice was stopped
+    pub(crate) fn is_done(&self) -> bool {
+        self.controls.stopped.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Voice {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// what a mixer mixes as an endless source, for a device to pull from
+pub(crate) struct MixerSource {
+    mixer: Arc<Mixer>,
+    buffer: Vec<f32>,
+    // index of the next sample in the buffer
+    pos: usize,
+}
+
+impl MixerSource {
+    pub(crate) fn new(mixer: Arc<Mixer>) -> Self {
+        Self {
+            mixer,
+            buffer: vec![0.0; BLOCK_FRAMES * CHANNELS as usize],
+            pos: BLOCK_FRAMES * CHANNELS as usize,
+        }
+    }
+}
+
+impl Iterator for MixerSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.pos == self.buffer.len() {
+            self.buffer.iter_mut().for_each(|sample| *sample = 0.0);
+            self.mixer.mix(&mut self.buffer);
+            self.pos = 0;
+        }
+        self.pos += 1;
+        Some(self.buffer[self.pos - 1])
+    }
+}
+
+impl Source for MixerSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        CHANNELS
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
@@ -0,0 +1,97 @@
+use rodio::Source;
+use std::time::Duration;
+
+// length of the delay line the read heads sweep through
+const WINDOW: Duration = Duration::from_millis(40);
+
+// shifts the pitch of a source without changing its length
+// two read heads sweep through a short delay line at the shifted rate and are crossfaded
+// so that one of them is always far away from the point where it jumps back
+pub(crate) struct PitchShift<S> {
+    inner: S,
+    channels: usize,
+    ratio: f64,
+    // length of the sweep in frames
+    window: f64,
+    // frames stored per channel
+    len: usize,
+    buffer: Vec<f32>,
+    // frame the current samples are written to
+    frame: usize,
+    // delay of the first read head in frames, between 0 and window
+    delay: f64,
+    // channel of the next sample
+    channel: usize,
+}
+
+impl<S: Source<Item = f32>> PitchShift<S> {
+    // semitones above (or below if negative) the original pitch
+    pub(crate) fn new(inner: S, semitones: f64) -> Self {
+        let channels = inner.channels().max(1) as usize;
+        let window = (WINDOW.as_secs_f64() * inner.sample_rate() as f64).max(2.0);
+        let len = window as usize + 2;
+
+        Self {
+            channels,
+            ratio: 2f64.powf(semitones / 12.0),
+            window,
+            len,
+            buffer: vec![0.0; len * channels],
+            frame: 0,
+            delay: 0.0,
+            channel: 0,
+            inner,
+        }
+    }
+
+    // the delayed sample of a read head, faded in and out over its sweep
+    fn tap(&self, delay: f64, channel: usize) -> f32 {
+        let gain = 1.0 - (2.0 * delay / self.window - 1.0).abs();
+        let pos = (self.frame as f64 - delay).rem_euclid(self.len as f64);
+        let index = pos.floor() as usize % self.len;
+        let next = (index + 1) % self.len;
+        let frac = (pos - pos.floor()) as f32;
+        let sample = self.buffer[index * self.channels + channel] * (1.0 - frac)
+            + self.buffer[next * self.channels + channel] * frac;
+        sample * gain as f32
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for PitchShift<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.inner.next()?;
+
+        if self.channel == 0 {
+            self.frame = (self.frame + 1) % self.len;
+            self.delay = (self.delay + 1.0 - self.ratio).rem_euclid(self.window);
+        }
+
+        let channel = self.channel;
+        self.buffer[self.frame * self.channels + channel] = sample;
+        let other_delay = (self.delay + self.window / 2.0) % self.window;
+        let out = self.tap(self.delay, channel) + self.tap(other_delay, channel);
+
+        self.channel = (self.channel + 1) % self.channels;
+        Some(out)
+    }
+}
+
+impl<S: Source<Item = f32>> Source for PitchShift<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
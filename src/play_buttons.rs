@@ -1,25 +1,130 @@
 use crate::add_view::AddViewMessage;
+use crate::audio_engine::AudioEngine;
 use crate::audio_settings::AudioSettings;
-use crate::sound_player::{PlayState, PlayerMessage, Sound};
+use crate::loudness;
+use crate::loudness::Loudness;
+use crate::sample_cache;
+use crate::sample_cache::SampleCache;
+use crate::hold;
+use crate::hold::Hold;
+use crate::sound_player::{PlayState, PlaybackMode, PlayerMessage, Sound, MAX_PITCH, MAX_SPEED, MIN_SPEED};
 use crate::Message;
 use crate::{sound_player, WindowSettings};
 
+use iced::keyboard::{KeyCode, Modifiers};
 use iced::{
-    button, Button, Column, Element, HorizontalAlignment, Length, Row, Text,VerticalAlignment,
+    button, container, slider, tooltip, Align, Background, Button, Column, Command, Element,
+    HorizontalAlignment, Length, ProgressBar, Row, Slider, Text, Tooltip, VerticalAlignment,
 };
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::path::{Path, PathBuf};
 use std::fmt::{Debug};
 
-
 use std::sync::mpsc::{Receiver, Sender};
 use std::sync::{Arc, Mutex};
 
+// how many playback failures the error log keeps
+const MAX_LOGGED_ERRORS: usize = 10;
+
+// what pressing a button does while its sound is still playing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub(crate) enum RetriggerMode {
+    // stop the sound
+    #[default]
+    Toggle,
+    // stop the sound and play it again from the start
+    Restart,
+    // play another instance on top of the playing ones
+    Overlap,
+    // keep playing, the press does nothing
+    Ignore,
+}
+
+impl RetriggerMode {
+    pub(crate) const ALL: [RetriggerMode; 4] = [
+        RetriggerMode::Toggle,
+        RetriggerMode::Restart,
+        RetriggerMode::Overlap,
+        RetriggerMode::Ignore,
+    ];
+}
+
+impl std::fmt::Display for RetriggerMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            RetriggerMode::Toggle => "press again to stop",
+            RetriggerMode::Restart => "press again to restart",
+            RetriggerMode::Overlap => "press again to overlap",
+            RetriggerMode::Ignore => "ignore presses while playing",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// how a button behaves besides the sound it plays, saved with the board
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct ButtonOptions {
+    // starting a button stops every other playing button with the same group, empty for no group
+    #[serde(default)]
+    pub(crate) choke_group: String,
+    #[serde(default)]
+    pub(crate) retrigger: RetriggerMode,
+    // name of the key that presses the button as returned by key_name, empty for none
+    #[serde(default)]
+    pub(crate) hotkey: String,
+}
+
+// the name a hotkey is stored as, the digit keys are named by their digit
+pub(crate) fn key_name(key_code: KeyCode) -> String {
+    let digit = match key_code {
+        KeyCode::Key0 | KeyCode::Numpad0 => '0',
+        KeyCode::Key1 | KeyCode::Numpad1 => '1',
+        KeyCode::Key2 | KeyCode::Numpad2 => '2',
+        KeyCode::Key3 | KeyCode::Numpad3 => '3',
+        KeyCode::Key4 | KeyCode::Numpad4 => '4',
+        KeyCode::Key5 | KeyCode::Numpad5 => '5',
+        KeyCode::Key6 | KeyCode::Numpad6 => '6',
+        KeyCode::Key7 | KeyCode::Numpad7 => '7',
+        KeyCode::Key8 | KeyCode::Numpad8 => '8',
+        KeyCode::Key9 | KeyCode::Numpad9 => '9',
+        _ => return format!("{:?}", key_code).to_uppercase(),
+    };
+    digit.to_string()
+}
+
+// a running player of a button
+pub(crate) struct PlayerHandle {
+    sender: Sender<PlayerMessage>,
+    receiver: Receiver<PlayState>,
+    state: PlayState,
+    // paused because an output device went missing, resumed once it is back
+    paused_by_device: bool,
+}
+
 pub(crate) struct PlayButton {
-    pub(crate) player_handle_sender: Option<Sender<sound_player::PlayerMessage>>,
-    pub(crate) player_handle_receiver: Option<Receiver<sound_player::PlayState>>,
+    // every instance of the sound that is still playing, the newest one last
+    pub(crate) players: Vec<PlayerHandle>,
     pub(crate) play_state: button::State,
+    hold_state: hold::State,
     pub(crate) delete_state: button::State,
+    export_state: button::State,
     pub(crate) sound: sound_player::Sound,
     pub(crate)name: String,
+    pub(crate) options: ButtonOptions,
+    // volume of this button from 0 to 100, shared with its players
+    pub(crate) volume: Arc<Mutex<i32>>,
+    volume_slider: slider::State,
+    // whether the speed and pitch sliders are shown below the button
+    tuning: bool,
+    tune_state: button::State,
+    speed_slider: slider::State,
+    pitch_slider: slider::State,
+    random_pitch_slider: slider::State,
+    // index of the file the last press played, the next file is picked based on it
+    last_file: Option<usize>,
+    // why the last player failed, the button shows it is broken until a press plays again
+    pub(crate) error: Option<String>,
 }
 
 impl PlayButton {
@@ -27,10 +132,136 @@ impl PlayButton {
         Self {
             name,
             sound,
+            options: Default::default(),
             play_state: Default::default(),
+            hold_state: Default::default(),
             delete_state: Default::default(),
-            player_handle_sender: None,
-            player_handle_receiver: None,
+            export_state: Default::default(),
+            players: vec![],
+            volume: Arc::new(Mutex::new(100)),
+            volume_slider: Default::default(),
+            tuning: false,
+            tune_state: Default::default(),
+            speed_slider: Default::default(),
+            pitch_slider: Default::default(),
+            random_pitch_slider: Default::default(),
+            last_file: None,
+            error: None,
+        }
+    }
+
+    // applies the latest states reported by the players and drops the ones that stopped, failed or found no output
+    // the button shows the state of its newest player, returns why players failed
+    pub(crate) fn poll_players(&mut self) -> Vec<String> {
+        let mut errors = vec![];
+        for player in self.players.iter_mut() {
+            for state in player.receiver.try_iter() {
+                match &state {
+                    PlayState::Error(reason) => errors.push(reason.clone()),
+                    PlayState::Playing { .. } => self.error = None,
+                    _ => {}
+                }
+                player.state = state;
+            }
+        }
+        if let Some(reason) = errors.last() {
+            self.error = Some(reason.clone());
+        }
+        self.players.retain(|player| {
+            !matches!(player.state, PlayState::Stopped | PlayState::NoOutput | PlayState::Error(_))
+        });
+        self.sound.state = self
+            .players
+            .last()
+            .map_or(PlayState::Stopped, |player| player.state.clone());
+        errors
+    }
+
+    pub(crate) fn send_all(&self, msg: PlayerMessage) {
+        for player in self.players.iter() {
+            let _ = player.sender.send(msg);
+        }
+    }
+}
+
+// a path for the export of a button next to the file it was rendered from, never an existing file
+fn export_path(file_path: &str, name: &str) -> PathBuf {
+    let file_path = Path::new(file_path);
+    let dir = file_path.parent().unwrap_or_else(|| Path::new(""));
+    let stem = file_path
+        .file_stem()
+        .map_or("sound".into(), |stem| stem.to_string_lossy());
+    let name: String = name
+        .chars()
+        .filter(|c| c.is_alphanumeric() || " -_".contains(*c))
+        .collect();
+    let name = if name.trim().is_empty() { "export" } else { name.trim() };
+
+    let mut path = dir.join(format!("{} - {}.wav", stem, name));
+    let mut number = 2;
+    while path.exists() {
+        path = dir.join(format!("{} - {} ({}).wav", stem, name, number));
+        number += 1;
+    }
+    path
+}
+
+// highlights buttons whose sound is currently playing
+struct PlayingStyle;
+
+impl button::StyleSheet for PlayingStyle {
+    fn active(&self) -> button::Style {
+        button::Style {
+            background: Some(Background::Color([0.6, 0.85, 0.6].into())),
+            border_radius: 2.0,
+            border_width: 1.0,
+            border_color: [0.4, 0.65, 0.4].into(),
+            ..button::Style::default()
+        }
+    }
+}
+
+// highlights buttons whose sound is paused
+struct PausedStyle;
+
+impl button::StyleSheet for PausedStyle {
+    fn active(&self) -> button::Style {
+        button::Style {
+            background: Some(Background::Color([0.95, 0.85, 0.5].into())),
+            border_radius: 2.0,
+            border_width: 1.0,
+            border_color: [0.75, 0.65, 0.3].into(),
+            ..button::Style::default()
+        }
+    }
+}
+
+// marks buttons whose last press could not be played
+struct BrokenStyle;
+
+impl button::StyleSheet for BrokenStyle {
+    fn active(&self) -> button::Style {
+        button::Style {
+            background: Some(Background::Color([0.95, 0.6, 0.6].into())),
+            border_radius: 2.0,
+            border_width: 1.0,
+            border_color: [0.75, 0.35, 0.35].into(),
+            ..button::Style::default()
+        }
+    }
+}
+
+// keeps the reason a button is broken readable on top of the other buttons
+struct ErrorTooltipStyle;
+
+impl container::StyleSheet for ErrorTooltipStyle {
+    fn style(&self) -> container::Style {
+        container::Style {
+            background: Some(Background::Color([1.0, 1.0, 1.0].into())),
+            border_radius: 2.0,
+            border_width: 1.0,
+            border_color: [0.75, 0.35, 0.35].into(),
+            ..container::Style::default()
         }
     }
 }
@@ -39,17 +270,44 @@ impl PlayButton {
 pub(crate) enum ButtonMessage {
     PlayButtonPressed(usize),
     DeleteButtonPressed(usize),
-    ButtonAdded(Sound, String), //sound and name
+    ExportPressed(usize),
+    Exported(Result<String, String>), //path of the written file or why it failed
+    ButtonAdded(Box<Sound>, ButtonOptions, String), //sound, options and name
+    ModifiersChanged(Modifiers),
+    Analyzed(String, Option<Loudness>), //file path and its loudness
+    VolumeChanged(usize, i32),
+    TuneToggled(usize),
+    SpeedChanged(usize, f64),
+    PitchChanged(usize, f64), //semitones
+    RandomPitchChanged(usize, f64), //semitones up or down
+    GatePressed(usize),
+    GateReleased(usize),
+    KeyPressed(KeyCode),
+    KeyReleased(KeyCode),
+    ClearErrorLog,
 }
 
 pub(crate) struct PlayButtons {
     pub(crate) buttons: Vec<PlayButton>,
     pub(crate) audio_settings: Arc<Mutex<AudioSettings>>,
+    pub(crate) audio_engine: Arc<Mutex<AudioEngine>>,
+    pub(crate) sample_cache: Arc<Mutex<SampleCache>>,
     pub(crate) video_settings: Arc<Mutex<WindowSettings>>,
     add_button: button::State,
     save_button: button::State,
+    stop_all_button: button::State,
     button_row_len: usize,
     is_being_added: bool,
+    modifiers: Modifiers,
+    // outcome of the last export or why nothing plays, shown below the buttons
+    status: Option<String>,
+    // an output has no device, sounds are paused and presses do nothing until it is back
+    output_lost: bool,
+    // the latest playback failures with the name of their button, the newest first
+    error_log: VecDeque<String>,
+    clear_log_button: button::State,
+    // keys that are down, the keyboard repeats presses while a key is held
+    held_keys: HashSet<KeyCode>,
 }
 
 impl Default for PlayButtons {
@@ -57,59 +315,323 @@ impl Default for PlayButtons {
         Self {
             buttons: vec![],
             audio_settings: Default::default(),
+            audio_engine: Default::default(),
+            sample_cache: Default::default(),
             video_settings: Default::default(),
             add_button: Default::default(),
             save_button: Default::default(),
+            stop_all_button: Default::default(),
             button_row_len: 5,
             is_being_added: false,
+            modifiers: Default::default(),
+            held_keys: Default::default(),
+            status: None,
+            output_lost: false,
+            error_log: VecDeque::new(),
+            clear_log_button: Default::default(),
         }
     }
 }
 
 impl PlayButtons {
-    pub(crate) fn update(&mut self, msg: ButtonMessage) {
+    pub(crate) fn update(&mut self, msg: ButtonMessage) -> Command<Message> {
         match msg {
+            //gated buttons react to the mouse going down and up instead
             ButtonMessage::PlayButtonPressed(index) => {
-                let btn = &mut self.buttons[index];
+                if self.buttons[index].sound.mode != PlaybackMode::Gate {
+                    self.press(index);
+                }
+            }
+
+            ButtonMessage::GatePressed(index) => self.gate_press(index),
+
+            ButtonMessage::GateReleased(index) => self.gate_release(index),
 
-                //check for messages from players
-                if let Some(tx) = &btn.player_handle_receiver {
-                    let res = tx.try_recv();
-                    if let Ok(msg) = res {
-                        match msg {
-                            PlayState::Stopped => btn.sound.state = PlayState::Stopped,
-                            PlayState::Playing => btn.sound.state = PlayState::Playing,
+            ButtonMessage::KeyPressed(key_code) => {
+                if self.held_keys.insert(key_code) {
+                    for index in self.hotkey_buttons(key_code) {
+                        if self.buttons[index].sound.mode == PlaybackMode::Gate {
+                            self.gate_press(index);
+                        } else {
+                            self.press(index);
                         }
                     }
                 }
+            }
+
+            ButtonMessage::KeyReleased(key_code) => {
+                self.held_keys.remove(&key_code);
+                for index in self.hotkey_buttons(key_code) {
+                    self.gate_release(index);
+                }
+            }
 
-                match btn.sound.state {
-                    PlayState::Playing => {
-                        btn.player_handle_sender
-                            .as_ref()
-                            .unwrap()
-                            .send(PlayerMessage::Stop); //unwrap because the handle must exist if the sound is playing
+            ButtonMessage::ExportPressed(index) => return self.export(index),
+
+            ButtonMessage::ClearErrorLog => self.error_log.clear(),
+
+            ButtonMessage::Exported(result) => {
+                self.status = Some(match result {
+                    Ok(path) => format!("exported to {}", path),
+                    Err(reason) => format!("export failed: {}", reason),
+                });
+            }
+
+            ButtonMessage::DeleteButtonPressed(index) => {
+                self.buttons[index].send_all(PlayerMessage::Stop);
+                let removed = self.buttons.remove(index);
+
+                //free the decoded samples of the files no other button uses
+                for path in removed.sound.paths() {
+                    if !self.buttons.iter().any(|btn| btn.sound.paths().any(|other| other == path)) {
+                        self.sample_cache.lock().unwrap().remove(path);
                     }
-                    PlayState::Stopped => {
-                        let (tx, rx) = btn.sound.play(self.audio_settings.clone());
-                        btn.player_handle_sender = Option::Some(tx);
-                        btn.player_handle_receiver = Option::Some(rx);
+                }
+            }
+            ButtonMessage::VolumeChanged(index, volume) => {
+                let btn = &self.buttons[index];
+                *btn.volume.lock().unwrap() = volume;
+                btn.send_all(PlayerMessage::SettingsChange);
+            }
+            ButtonMessage::TuneToggled(index) => {
+                let btn = &mut self.buttons[index];
+                btn.tuning = !btn.tuning;
+            }
+            //players copy the sound when they start, so the new values apply from the next press on
+            ButtonMessage::SpeedChanged(index, speed) => {
+                self.buttons[index].sound.speed = speed;
+            }
+            ButtonMessage::PitchChanged(index, pitch) => {
+                self.buttons[index].sound.pitch = pitch;
+            }
+            ButtonMessage::RandomPitchChanged(index, range) => {
+                self.buttons[index].sound.random_pitch = range;
+            }
+            ButtonMessage::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers;
+            }
+            ButtonMessage::Analyzed(path, loudness) => {
+                for btn in self.buttons.iter_mut() {
+                    for file in btn.sound.all_files_mut().filter(|file| file.path == path) {
+                        file.loudness = loudness;
                     }
                 }
             }
+            ButtonMessage::ButtonAdded(sound, options, name) => {
+                let analysis =
+                    Command::batch(sound.paths().map(|path| self.analyze(path.clone())).collect::<Vec<_>>());
+                self.buttons.push(PlayButton {
+                    options,
+                    ..PlayButton::new(*sound, name)
+                });
+                return analysis;
+            }
+        }
+        self.sync_ducking();
+        Command::none()
+    }
 
-            ButtonMessage::DeleteButtonPressed(index) => {
-                if let Some(handle) = &self.buttons[index].player_handle_sender {
-                    handle.send(PlayerMessage::Stop);
+    // a press of a button that is not gated
+    fn press(&mut self, index: usize) {
+        self.poll_button(index);
+        let btn = &self.buttons[index];
+
+        if btn.players.is_empty() {
+            self.trigger(index);
+        } else if self.modifiers.shift {
+            //holding shift toggles pause instead of retriggering
+            match btn.sound.state {
+                PlayState::Paused { .. } => btn.send_all(PlayerMessage::Resume),
+                _ => btn.send_all(PlayerMessage::Pause),
+            }
+        } else {
+            match btn.options.retrigger {
+                //one-shot sounds can only be stopped by choke groups and stop all
+                RetriggerMode::Toggle if btn.sound.mode == PlaybackMode::OneShot => {}
+                RetriggerMode::Toggle => btn.send_all(PlayerMessage::Stop),
+                RetriggerMode::Restart => {
+                    btn.send_all(PlayerMessage::Stop);
+                    self.trigger(index);
                 }
-                self.buttons.remove(index);
+                RetriggerMode::Overlap => self.trigger(index),
+                RetriggerMode::Ignore => {}
+            }
+        }
+    }
+
+    // a gated button went down, it plays from the start until it is released
+    fn gate_press(&mut self, index: usize) {
+        if self.buttons[index].sound.mode == PlaybackMode::Gate {
+            self.buttons[index].send_all(PlayerMessage::Stop);
+            self.trigger(index);
+        }
+    }
+
+    fn gate_release(&mut self, index: usize) {
+        let btn = &self.buttons[index];
+        if btn.sound.mode == PlaybackMode::Gate {
+            btn.send_all(PlayerMessage::Stop);
+        }
+    }
+
+    // indices of the buttons pressed by the key
+    fn hotkey_buttons(&self, key_code: KeyCode) -> Vec<usize> {
+        let name = key_name(key_code);
+        self.buttons
+            .iter()
+            .enumerate()
+            .filter(|(_, btn)| btn.options.hotkey == name)
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    // starts a new player for the button at index after stopping the rest of its choke group
+    fn trigger(&mut self, index: usize) {
+        if self.output_lost {
+            return;
+        }
+        self.choke(index);
+
+        let btn = &mut self.buttons[index];
+        if btn.sound.files.is_empty() {
+            return;
+        }
+        let file_index = btn.sound.pick_file(btn.last_file);
+        btn.last_file = Some(file_index);
+        let (sender, receiver) = btn.sound.play(
+            file_index,
+            self.audio_settings.clone(),
+            self.audio_engine.clone(),
+            self.sample_cache.clone(),
+            btn.volume.clone(),
+        );
+        btn.players.push(PlayerHandle {
+            sender,
+            receiver,
+            //counts as playing until the player reports otherwise
+            state: PlayState::Playing {
+                position: Default::default(),
+                duration: None,
+            },
+            paused_by_device: false,
+        });
+        btn.sound.state = btn.players.last().unwrap().state.clone();
+    }
+
+    // stops the other playing buttons in the choke group of the button at index
+    fn choke(&mut self, index: usize) {
+        let group = &self.buttons[index].options.choke_group;
+        if group.is_empty() {
+            return;
+        }
+
+        for (other_index, btn) in self.buttons.iter().enumerate() {
+            if other_index != index && &btn.options.choke_group == group {
+                btn.send_all(PlayerMessage::Stop);
             }
-            ButtonMessage::ButtonAdded(sound, name) => {
-                self.buttons.push(PlayButton::new(sound, name))
+        }
+    }
+
+    // pauses the playing sounds while an output has no device and resumes them once it is back
+    pub(crate) fn set_output_lost(&mut self, lost: bool) {
+        if lost == self.output_lost {
+            return;
+        }
+        self.output_lost = lost;
+        self.status = if lost {
+            Some("an output device is missing, playback is paused until it is back".to_string())
+        } else {
+            None
+        };
+
+        for index in 0..self.buttons.len() {
+            self.poll_button(index);
+            let btn = &mut self.buttons[index];
+            for player in btn.players.iter_mut() {
+                if lost && matches!(player.state, PlayState::Playing { .. }) {
+                    let _ = player.sender.send(PlayerMessage::Pause);
+                    player.paused_by_device = true;
+                } else if !lost && player.paused_by_device {
+                    let _ = player.sender.send(PlayerMessage::Resume);
+                    player.paused_by_device = false;
+                }
             }
         }
     }
 
+    // channels to every running player, used to tell them about changed settings
+    pub(crate) fn player_channels(&self) -> Vec<Sender<PlayerMessage>> {
+        self.buttons
+            .iter()
+            .flat_map(|btn| btn.players.iter().map(|player| player.sender.clone()))
+            .collect()
+    }
+
+    // renders the first file of the button at index in the background and writes it next to that file
+    // the result arrives as ButtonMessage::Exported
+    fn export(&self, index: usize) -> Command<Message> {
+        let btn = &self.buttons[index];
+        let sound = btn.sound.clone();
+        let name = btn.name.clone();
+        let volume = *btn.volume.lock().unwrap();
+        let settings = self.audio_settings.lock().unwrap().clone();
+        let cache = self.sample_cache.clone();
+        Command::perform(
+            async move {
+                let file_path = &sound.files.first().ok_or("the button has no file")?.path;
+                let rendered = sound.render(0, &settings, &cache, volume)?;
+                let path = export_path(file_path, &name);
+                rendered.write_wav(&path).map_err(|err| err.to_string())?;
+                Ok(path.display().to_string())
+            },
+            |result: Result<String, String>| Message::PlayButtons(ButtonMessage::Exported(result)),
+        )
+    }
+
+    // decodes and measures the file in the background, the result arrives as ButtonMessage::Analyzed
+    pub(crate) fn analyze(&self, path: String) -> Command<Message> {
+        let cache = self.sample_cache.clone();
+        Command::perform(
+            async move {
+                let loudness =
+                    sample_cache::load(&cache, &path).ok().map(|decoded| loudness::analyze(&decoded));
+                (path, loudness)
+            },
+            |(path, loudness)| Message::PlayButtons(ButtonMessage::Analyzed(path, loudness)),
+        )
+    }
+
+    // applies the states reported by all players since the last poll
+    pub(crate) fn poll_players(&mut self) {
+        for index in 0..self.buttons.len() {
+            self.poll_button(index);
+        }
+        self.sync_ducking();
+    }
+
+    // polls the players of one button and logs why any of them failed
+    fn poll_button(&mut self, index: usize) {
+        let btn = &mut self.buttons[index];
+        for reason in btn.poll_players() {
+            self.error_log.push_front(format!("{}: {}", btn.name, reason));
+        }
+        self.error_log.truncate(MAX_LOGGED_ERRORS);
+    }
+
+    // ducks the microphone passthrough while any button shows that it is playing
+    fn sync_ducking(&self) {
+        let playing = self
+            .buttons
+            .iter()
+            .any(|btn| matches!(btn.sound.state, PlayState::Playing { .. }));
+        self.audio_engine.lock().unwrap().set_ducked(playing);
+    }
+
+    // whether any button still has a player that reports its state
+    pub(crate) fn has_active_players(&self) -> bool {
+        self.buttons.iter().any(|btn| !btn.players.is_empty())
+    }
+
     pub(crate) fn view(&mut self) -> Element<'_, Message> {
         let settings = self.video_settings.lock().unwrap();
         let (width, height) = (settings.width, settings.height);
@@ -127,45 +649,151 @@ impl PlayButtons {
 
             //add play buttons to temp slice
             for (index, button) in self.buttons.iter_mut().enumerate() {
-                row_children.push(
-                    //add play + remove buttons
-                    Column::new()
-                        .push(
-                            Row::new()
-                                .push(
-                                    Button::new(
-                                        &mut button.play_state,
-                                        Text::new(&button.name)
-                                            .horizontal_alignment(HorizontalAlignment::Center)
-                                            .vertical_alignment(VerticalAlignment::Center),
-                                    )
-                                    .width(Length::from(button_width as u16))
-                                    .height(Length::from(button_height as u16))
-                                    .on_press(
-                                        Message::PlayButtons(ButtonMessage::PlayButtonPressed(
-                                            index,
-                                        )),
-                                    ),
+                //add play + remove buttons
+                let column = Column::new()
+                    .push(
+                        Row::new()
+                            .push({
+                                let label = if button.options.hotkey.is_empty() {
+                                    button.name.clone()
+                                } else {
+                                    format!("{} ({})", button.name, button.options.hotkey)
+                                };
+                                let play_button = Button::new(
+                                    &mut button.play_state,
+                                    Text::new(label)
+                                        .horizontal_alignment(HorizontalAlignment::Center)
+                                        .vertical_alignment(VerticalAlignment::Center),
                                 )
-                                .push(
-                                    Button::new(
-                                        &mut button.delete_state,
-                                        Text::new("X")
-                                            .horizontal_alignment(HorizontalAlignment::Center)
-                                            .vertical_alignment(VerticalAlignment::Center)
-                                            .size((button_height / 4) as u16),
+                                .width(Length::from(button_width as u16))
+                                .height(Length::from(button_height as u16))
+                                .on_press(
+                                    Message::PlayButtons(ButtonMessage::PlayButtonPressed(
+                                        index,
+                                    )),
+                                );
+
+                                let play_button = match button.sound.state {
+                                    PlayState::Playing { .. } => play_button.style(PlayingStyle),
+                                    PlayState::Paused { .. } => play_button.style(PausedStyle),
+                                    _ if button.error.is_some() => play_button.style(BrokenStyle),
+                                    PlayState::Stopped | PlayState::NoOutput | PlayState::Error(_) => play_button,
+                                };
+
+                                let play_button: Element<'_, _> =
+                                    if button.sound.mode == PlaybackMode::Gate {
+                                        Hold::new(
+                                            &mut button.hold_state,
+                                            play_button,
+                                            Message::PlayButtons(ButtonMessage::GatePressed(index)),
+                                            Message::PlayButtons(ButtonMessage::GateReleased(index)),
+                                        )
+                                        .into()
+                                    } else {
+                                        play_button.into()
+                                    };
+                                match &button.error {
+                                    Some(reason) => {
+                                        Tooltip::new(play_button, reason, tooltip::Position::Bottom)
+                                            .style(ErrorTooltipStyle)
+                                            .padding(5)
+                                            .into()
+                                    }
+                                    None => play_button,
+                                }
+                            })
+                            .push(
+                                Column::new()
+                                    .push(
+                                        Button::new(
+                                            &mut button.delete_state,
+                                            Text::new("X")
+                                                .horizontal_alignment(HorizontalAlignment::Center)
+                                                .vertical_alignment(VerticalAlignment::Center)
+                                                .size((button_height / 4) as u16),
+                                        )
+                                        .min_height((button_height / 2) as u32)
+                                        .min_width((button_width / 8) as u32)
+                                        .on_press(
+                                            Message::PlayButtons(ButtonMessage::DeleteButtonPressed(
+                                                index,
+                                            )),
+                                        ),
                                     )
-                                    .min_height(button_height as u32)
-                                    .min_width((button_width / 8) as u32)
-                                    .on_press(
-                                        Message::PlayButtons(ButtonMessage::DeleteButtonPressed(
-                                            index,
-                                        )),
+                                    .push(
+                                        Button::new(
+                                            &mut button.export_state,
+                                            Text::new("wav")
+                                                .horizontal_alignment(HorizontalAlignment::Center)
+                                                .vertical_alignment(VerticalAlignment::Center)
+                                                .size((button_height / 6) as u16),
+                                        )
+                                        .min_height((button_height / 2) as u32)
+                                        .min_width((button_width / 8) as u32)
+                                        .on_press(
+                                            Message::PlayButtons(ButtonMessage::ExportPressed(
+                                                index,
+                                            )),
+                                        ),
                                     ),
-                                ),
+                            ),
+                    )
+                    .push({
+                        let volume = *button.volume.lock().unwrap();
+                        Slider::new(&mut button.volume_slider, 0..=100, volume, move |val| {
+                            Message::PlayButtons(ButtonMessage::VolumeChanged(index, val))
+                        })
+                        .step(1)
+                        .width(Length::from(button_width as u16))
+                    })
+                    .push(
+                        ProgressBar::new(0.0..=1.0, button.sound.state.progress())
+                            .width(Length::from(button_width as u16))
+                            .height(Length::from(4)),
+                    )
+                    .push(
+                        Button::new(
+                            &mut button.tune_state,
+                            Text::new(if button.tuning { "done" } else { "tune" })
+                                .size((button_height / 6) as u16),
                         )
-                        .into(),
-                );
+                        .on_press(Message::PlayButtons(ButtonMessage::TuneToggled(index))),
+                    );
+
+                //speed and pitch of the button, shown only while it is being tuned
+                let column = if button.tuning {
+                    let speed = button.sound.clamped_speed();
+                    let pitch = button.sound.base_pitch();
+                    let range = button.sound.pitch_range();
+                    column
+                        .push(Text::new(format!("speed {:.2}x", speed)).size((button_height / 6) as u16))
+                        .push(
+                            Slider::new(&mut button.speed_slider, MIN_SPEED..=MAX_SPEED, speed, move |val| {
+                                Message::PlayButtons(ButtonMessage::SpeedChanged(index, val))
+                            })
+                            .step(0.05)
+                            .width(Length::from(button_width as u16)),
+                        )
+                        .push(Text::new(format!("pitch {:+.1} st", pitch)).size((button_height / 6) as u16))
+                        .push(
+                            Slider::new(&mut button.pitch_slider, -MAX_PITCH..=MAX_PITCH, pitch, move |val| {
+                                Message::PlayButtons(ButtonMessage::PitchChanged(index, val))
+                            })
+                            .step(0.5)
+                            .width(Length::from(button_width as u16)),
+                        )
+                        .push(Text::new(format!("random pitch \u{b1}{:.1} st", range)).size((button_height / 6) as u16))
+                        .push(
+                            Slider::new(&mut button.random_pitch_slider, 0.0..=MAX_PITCH, range, move |val| {
+                                Message::PlayButtons(ButtonMessage::RandomPitchChanged(index, val))
+                            })
+                            .step(0.5)
+                            .width(Length::from(button_width as u16)),
+                        )
+                } else {
+                    column
+                };
+                row_children.push(column.into());
             }
             if !self.is_being_added {
                 //add "add" button
@@ -199,8 +827,22 @@ impl PlayButtons {
                     .into(),
             );
 
+            //add stop all button
+            row_children.push(
+                Button::new(
+                    &mut self.stop_all_button,
+                    Text::new("stop all (esc)")
+                        .horizontal_alignment(HorizontalAlignment::Center)
+                        .vertical_alignment(VerticalAlignment::Center),
+                )
+                    .on_press(Message::StopAll)
+                    .width(Length::from(button_width as u16))
+                    .height(Length::from(button_height as u16))
+                    .into(),
+            );
+
             //calculate amount of rows to draw
-            let row_amount = if row_children.len() < self.button_row_len && row_children.len() > 0 {
+            let row_amount = if row_children.len() < self.button_row_len && !row_children.is_empty() {
                 1
             } else {
                 row_children.len() / self.button_row_len
@@ -212,7 +854,7 @@ impl PlayButtons {
             for _i in 0..row_amount + 1 {
                 let mut added_buttons = 0;
                 let mut temp_buttons: Vec<Element<'_, _>> = vec![];
-                while added_buttons < self.button_row_len && 0 < row_children.len() {
+                while added_buttons < self.button_row_len && !row_children.is_empty() {
                     if let Some(x) = row_children.pop() {
                         temp_buttons.push(x);
                     }
@@ -226,6 +868,24 @@ impl PlayButtons {
                         .into(),
                 );
             }
+            if let Some(status) = &self.status {
+                children.push(Text::new(status).into());
+            }
+            if !self.error_log.is_empty() {
+                let header = Row::new()
+                    .spacing(10)
+                    .align_items(Align::Center)
+                    .push(Text::new("recent errors"))
+                    .push(
+                        Button::new(&mut self.clear_log_button, Text::new("clear"))
+                            .on_press(Message::PlayButtons(ButtonMessage::ClearErrorLog)),
+                    );
+                let log = self.error_log.iter().fold(
+                    Column::new().spacing(2).padding(10).push(header),
+                    |log, entry| log.push(Text::new(entry).size(16)),
+                );
+                children.push(log.into());
+            }
             Column::with_children(children).into()
         } else {
             Column::new().into()
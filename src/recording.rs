@@ -0,0 +1,208 @@
+use crate::backend::{AudioBackend, Output};
+use crate::mic::{FileInput, InputSource};
+use crate::mixer::{Mixer, CHANNELS, SAMPLE_RATE};
+use crate::sample_cache::DecodedSound;
+use hound::{SampleFormat, WavSpec, WavWriter};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// how often the real time driver advances the outputs
+const TICK: Duration = Duration::from_millis(10);
+
+// plays into memory instead of a sound card, outputs only move forward when they are advanced
+// what each output device played is kept until it gets taken
+pub(crate) struct RecordingBackend {
+    output_names: Vec<String>,
+    opened: Mutex<Vec<OpenedOutput>>,
+    recorded: Mutex<HashMap<String, Vec<f32>>>,
+    // devices that are gone for now, they are not listed, opened or recorded
+    unplugged: Mutex<Vec<String>>,
+    // what every input device records, played in a loop, there are no inputs without it
+    input: Option<DecodedSound>,
+}
+
+// a mixer played on a device until its output gets dropped
+struct OpenedOutput {
+    dev_name: String,
+    mixer: Arc<Mixer>,
+    closed: Arc<AtomicBool>,
+    lost: Arc<AtomicBool>,
+}
+
+impl RecordingBackend {
+    // the first name is the default output, every input device opened records input in a loop
+    pub(crate) fn new(output_names: Vec<String>, input: Option<DecodedSound>) -> Self {
+        Self {
+            output_names,
+            opened: Default::default(),
+            recorded: Default::default(),
+            unplugged: Default::default(),
+            input,
+        }
+    }
+
+    // mixes the next frames of every open output, devices nothing is played on record silence
+    pub(crate) fn advance(&self, frames: usize) {
+        self.lose_unplugged();
+        let names = self.output_device_names();
+        let mut opened = self.opened.lock().unwrap();
+        opened.retain(|output| !output.closed.load(Ordering::Relaxed));
+
+        let mut recorded = self.recorded.lock().unwrap();
+        for name in names {
+            let mut mixed = vec![0.0; frames * CHANNELS as usize];
+            for output in opened.iter().filter(|output| output.dev_name == name) {
+                output.mixer.mix(&mut mixed);
+            }
+            recorded.entry(name).or_default().extend(mixed);
+        }
+    }
+
+    // takes the device away like pulling its cable, its open outputs are lost
+    #[cfg(test)]
+    pub(crate) fn unplug(&self, dev_name: &str) {
+        self.unplugged.lock().unwrap().push(dev_name.to_string());
+        self.lose_unplugged();
+    }
+
+    // the outputs open on a device that went away stop playing and report it
+    fn lose_unplugged(&self) {
+        let unplugged = self.unplugged.lock().unwrap();
+        for output in self.opened.lock().unwrap().iter() {
+            if unplugged.contains(&output.dev_name) {
+                output.lost.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    // brings an unplugged device back, outputs have to open it again
+    #[cfg(test)]
+    pub(crate) fn plug(&self, dev_name: &str) {
+        self.unplugged.lock().unwrap().retain(|name| name != dev_name);
+    }
+
+    // the interleaved samples the device with that name played since the last call
+    pub(crate) fn take_recorded(&self, dev_name: &str) -> Vec<f32> {
+        self.recorded
+            .lock()
+            .unwrap()
+            .remove(dev_name)
+            .unwrap_or_default()
+    }
+
+    // advances the outputs as fast as a sound card would play them until the backend is dropped
+    // and saves what they played to record_dir if it is given
+    pub(crate) fn run_in_real_time(backend: &Arc<Self>, record_dir: Option<PathBuf>) {
+        let backend: Weak<Self> = Arc::downgrade(backend);
+        thread::spawn(move || {
+            let started = Instant::now();
+            let mut advanced = 0;
+            let mut writers: HashMap<String, WavWriter<BufWriter<File>>> = HashMap::new();
+            let mut last_flush = Instant::now();
+
+            while let Some(backend) = backend.upgrade() {
+                let due = (started.elapsed().as_secs_f64() * SAMPLE_RATE as f64) as usize;
+                backend.advance(due - advanced);
+                advanced = due;
+
+                for name in backend.output_names.iter() {
+                    let recorded = backend.take_recorded(name);
+                    let dir = match &record_dir {
+                        Some(dir) => dir,
+                        None => continue,
+                    };
+                    if !writers.contains_key(name) {
+                        let spec = WavSpec {
+                            channels: CHANNELS,
+                            sample_rate: SAMPLE_RATE,
+                            bits_per_sample: 16,
+                            sample_format: SampleFormat::Int,
+                        };
+                        match WavWriter::create(dir.join(format!("{}.wav", name)), spec) {
+                            Ok(writer) => writers.insert(name.clone(), writer),
+                            Err(_) => continue,
+                        };
+                    }
+                    if let Some(writer) = writers.get_mut(name) {
+                        for sample in recorded {
+                            let _ = writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16);
+                        }
+                    }
+                }
+
+                //keep the headers up to date, the files are never finalized
+                if last_flush.elapsed() > Duration::from_secs(1) {
+                    for writer in writers.values_mut() {
+                        let _ = writer.flush();
+                    }
+                    last_flush = Instant::now();
+                }
+
+                drop(backend);
+                thread::sleep(TICK);
+            }
+        });
+    }
+}
+
+impl AudioBackend for RecordingBackend {
+    fn output_device_names(&self) -> Vec<String> {
+        let unplugged = self.unplugged.lock().unwrap();
+        self.output_names
+            .iter()
+            .filter(|name| !unplugged.contains(name))
+            .cloned()
+            .collect()
+    }
+
+    fn input_device_names(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn open_output(&self, dev_name: &str, mixer: Arc<Mixer>) -> Option<Box<dyn Output>> {
+        let names = self.output_device_names();
+        let name = if dev_name.is_empty() {
+            names.first()?
+        } else {
+            names.iter().find(|name| *name == dev_name)?
+        };
+        let closed = Arc::new(AtomicBool::new(false));
+        let lost = Arc::new(AtomicBool::new(false));
+        self.opened.lock().unwrap().push(OpenedOutput {
+            dev_name: name.clone(),
+            mixer,
+            closed: closed.clone(),
+            lost: lost.clone(),
+        });
+        Some(Box::new(RecordingOutput { closed, lost }))
+    }
+
+    // only the given input can be recorded
+    fn open_input(&self, _dev_name: &str) -> Option<Box<dyn InputSource>> {
+        let sound = self.input.clone()?;
+        Some(Box::new(FileInput::new(sound)))
+    }
+}
+
+struct RecordingOutput {
+    closed: Arc<AtomicBool>,
+    lost: Arc<AtomicBool>,
+}
+
+impl Output for RecordingOutput {
+    fn is_lost(&self) -> bool {
+        self.lost.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for RecordingOutput {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,65 @@
+use hound::{SampleFormat, WavSpec, WavWriter};
+use rodio::Source;
+use std::path::Path;
+
+// the samples a sound renders to, as they would reach an output at full volume
+pub(crate) struct Rendered {
+    pub(crate) channels: u16,
+    pub(crate) sample_rate: u32,
+    // interleaved samples of all channels
+    pub(crate) samples: Vec<f32>,
+}
+
+impl Rendered {
+    // plays the source to its end, it must not be endless
+    pub(crate) fn from_source<S: Source<Item = f32>>(source: S) -> Self {
+        let channels = source.channels();
+        let sample_rate = source.sample_rate();
+        Self {
+            channels,
+            sample_rate,
+            samples: source.collect(),
+        }
+    }
+
+    // writes the samples to a 16 bit WAV file, samples outside of -1 to 1 get clipped
+    pub(crate) fn write_wav(&self, path: &Path) -> Result<(), hound::Error> {
+        let spec = WavSpec {
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(path, spec)?;
+        for sample in self.samples.iter() {
+            writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)?;
+        }
+        writer.finalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TempFile;
+    use hound::WavReader;
+
+    #[test]
+    fn wav_round_trips() {
+        let rendered = Rendered {
+            channels: 2,
+            sample_rate: 8000,
+            samples: vec![0.0, 0.5, -0.5, 1.0, 1.5, -1.5],
+        };
+        let file = TempFile::new("wav");
+        rendered.write_wav(&file.path).unwrap();
+
+        let mut reader = WavReader::open(&file.path).unwrap();
+        let spec = reader.spec();
+        assert_eq!(spec.channels, 2);
+        assert_eq!(spec.sample_rate, 8000);
+        assert_eq!(spec.bits_per_sample, 16);
+        let samples: Vec<i16> = reader.samples::<i16>().map(Result::unwrap).collect();
+        assert_eq!(samples, vec![0, 16383, -16383, 32767, 32767, -32767]);
+    }
+}
@@ -0,0 +1,185 @@
+use rodio::{Decoder, Source};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// the fully decoded samples of a sound file, cloning only clones the reference to the samples
+#[derive(Clone)]
+pub(crate) struct DecodedSound {
+    channels: u16,
+    sample_rate: u32,
+    samples: Arc<Vec<f32>>,
+}
+
+impl DecodedSound {
+    // returns why the file can not be played if it can not be decoded
+    pub(crate) fn decode(path: &str) -> Result<Self, String> {
+        let file = File::open(path).map_err(|err| format!("{} could not be opened: {}", path, err))?;
+        let decoder = Decoder::new(BufReader::new(file))
+            .map_err(|err| format!("{} could not be decoded: {}", path, err))?;
+        let channels = decoder.channels();
+        let sample_rate = decoder.sample_rate();
+        //some decoders panic on corrupt data instead of ending early
+        let samples: Vec<f32> = panic::catch_unwind(AssertUnwindSafe(|| decoder.convert_samples().collect()))
+            .map_err(|_| format!("{} is corrupt", path))?;
+        if samples.is_empty() || channels == 0 || sample_rate == 0 {
+            return Err(format!("{} contains no sound", path));
+        }
+
+        Ok(Self {
+            channels,
+            sample_rate,
+            samples: Arc::new(samples),
+        })
+    }
+
+    // a sound made of the given interleaved samples instead of a file
+    #[cfg(test)]
+    pub(crate) fn from_samples(channels: u16, sample_rate: u32, samples: Vec<f32>) -> Self {
+        Self {
+            channels,
+            sample_rate,
+            samples: Arc::new(samples),
+        }
+    }
+
+    pub(crate) fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    pub(crate) fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    // the interleaved samples of all channels
+    pub(crate) fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    // returns a new source playing the samples between start and end (in seconds)
+    // a missing start plays from the beginning, a missing end plays until the end of the file
+    pub(crate) fn source(&self, start: Option<f64>, end: Option<f64>) -> SamplesSource {
+        let end = end.map_or(self.samples.len(), |end| self.sample_index(end));
+        let start = start.map_or(0, |start| self.sample_index(start)).min(end);
+
+        SamplesSource {
+            sound: self.clone(),
+            pos: start,
+            end,
+            loop_start: None,
+        }
+    }
+
+    // index of the first sample of the frame at the given time, clamped to the length of the sound
+    fn sample_index(&self, secs: f64) -> usize {
+        let frame = (secs.max(0.0) * self.sample_rate as f64) as usize;
+        //a time far past the end saturates the frame, it must not overflow
+        frame.saturating_mul(self.channels as usize).min(self.samples.len())
+    }
+}
+
+// plays the samples of a DecodedSound, every output gets its own source over the same samples
+pub(crate) struct SamplesSource {
+    sound: DecodedSound,
+    pos: usize,
+    end: usize,
+    // where to jump back to once the end is reached, None if the source does not loop
+    loop_start: Option<usize>,
+}
+
+impl SamplesSource {
+    // repeats the samples between the current position and the end until the source is dropped
+    pub(crate) fn looped(self) -> Self {
+        Self {
+            loop_start: Some(self.pos),
+            ..self
+        }
+    }
+}
+
+impl Iterator for SamplesSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.pos >= self.end {
+            match self.loop_start {
+                Some(loop_start) if loop_start < self.end => self.pos = loop_start,
+                _ => return None,
+            }
+        }
+        let sample = self.sound.samples.get(self.pos).copied();
+        self.pos += 1;
+        sample
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.end.saturating_sub(self.pos);
+        match self.loop_start {
+            Some(_) => (remaining, None),
+            None => (remaining, Some(remaining)),
+        }
+    }
+}
+
+impl Source for SamplesSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.sound.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sound.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        if self.loop_start.is_some() {
+            return None;
+        }
+        let frames = (self.end - self.pos) as u64 / self.sound.channels as u64;
+        Some(Duration::from_nanos(
+            frames * 1_000_000_000 / self.sound.sample_rate as u64,
+        ))
+    }
+}
+
+// decoded sounds keyed by their file path
+#[derive(Default)]
+pub(crate) struct SampleCache {
+    sounds: HashMap<String, DecodedSound>,
+}
+
+impl SampleCache {
+    pub(crate) fn remove(&mut self, path: &str) {
+        self.sounds.remove(path);
+    }
+}
+
+// returns the cached samples of the file, decodes and caches it if it was not loaded yet
+// the lock is not held while decoding so other sounds can still start playing
+pub(crate) fn load(cache: &Arc<Mutex<SampleCache>>, path: &str) -> Result<DecodedSound, String> {
+    if let Some(sound) = cache.lock().unwrap().sounds.get(path) {
+        return Ok(sound.clone());
+    }
+
+    let sound = DecodedSound::decode(path)?;
+    cache
+        .lock()
+        .unwrap()
+        .sounds
+        .insert(path.to_string(), sound.clone());
+    Ok(sound)
+}
+
+// decodes the file in the background so the first press does not have to wait for it
+pub(crate) fn preload(cache: Arc<Mutex<SampleCache>>, path: String) {
+    thread::spawn(move || {
+        let _ = load(&cache, &path);
+    });
+}
@@ -1,142 +1,910 @@
+use crate::audio_engine::AudioEngine;
 use crate::audio_settings::AudioSettings;
-use rodio::{Decoder, OutputStream, Sink, Source, DeviceTrait};
-use std::fs::File;
-use std::io::BufReader;
-use std::sync::mpsc::{Receiver, Sender};
+use crate::effects::{Effect, Effected};
+use crate::envelope::{Envelope, StopHandle};
+use crate::loudness::Loudness;
+use crate::pitch::PitchShift;
+use crate::render::Rendered;
+use crate::mixer::{Mixer, Voice};
+use crate::sample_cache;
+use crate::sample_cache::{DecodedSound, SampleCache};
+use rand::Rng;
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant};
 
+// how often the player checks whether its voices have drained and reports its position
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+// fade out used when a sound gets stopped, avoids the click of a hard cut
+const STOP_FADE: Duration = Duration::from_millis(50);
+// how much longer than the stop fade the player waits for the outputs to drain
+const STOP_GRACE: Duration = Duration::from_millis(250);
+// slowest and fastest playback rate of a sound
+pub(crate) const MIN_SPEED: f64 = 0.25;
+pub(crate) const MAX_SPEED: f64 = 4.0;
+// largest pitch shift in semitones
+pub(crate) const MAX_PITCH: f64 = 24.0;
+// largest boost or cut of a layer in dB, anything beyond is inaudible or clips anyway
+const MAX_LAYER_GAIN_DB: f64 = 40.0;
 
-use rodio::cpal::traits::HostTrait;
+// one of the files a sound can play
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct SoundFile {
+    pub(crate) path: String,
+    // measured when the file was added, used for loudness normalization
+    #[serde(default)]
+    pub(crate) loudness: Option<Loudness>,
+}
+
+impl SoundFile {
+    pub(crate) fn new(path: String) -> Self {
+        Self {
+            path,
+            loudness: None,
+        }
+    }
+
+    // gain that brings the file to the target loudness, 1 if normalization is off or it was not analyzed
+    fn normalization_gain(&self, settings: &AudioSettings) -> f32 {
+        match self.loudness {
+            Some(loudness) if settings.normalize_loudness => {
+                loudness.gain_to(settings.target_loudness as f64)
+            }
+            _ => 1.0,
+        }
+    }
+}
+
+// a file played together with the file picked for a press
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Layer {
+    #[serde(flatten)]
+    pub(crate) file: SoundFile,
+    // in dB, on top of the loudness normalization
+    #[serde(default)]
+    pub(crate) gain: f64,
+    // how long after the press the layer starts, in seconds
+    #[serde(default)]
+    pub(crate) offset: f64,
+}
+
+// the mix of a sound and its layers before the envelope is applied
+type LayeredSource = Box<dyn Source<Item = f32> + Send>;
+// the decoded file of a sound and the decoded files of its layers
+type Loaded<'a> = (DecodedSound, Vec<(&'a Layer, DecodedSound)>);
+
+// which file a sound with several files plays next
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub(crate) enum VariationMode {
+    // a random file, but never the same one twice in a row
+    #[default]
+    Random,
+    // the files in order, starting over after the last one
+    RoundRobin,
+}
+
+impl VariationMode {
+    pub(crate) const ALL: [VariationMode; 2] = [VariationMode::Random, VariationMode::RoundRobin];
+}
 
-#[derive(Debug, Clone)]
+impl std::fmt::Display for VariationMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            VariationMode::Random => "random file",
+            VariationMode::RoundRobin => "files in order",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct Sound {
-    pub(crate) file_path: String,
+    // every press plays one of them
+    pub(crate) files: Vec<SoundFile>,
+    #[serde(default)]
+    pub(crate) variation: VariationMode,
+    // played on top of the picked file, whole and only once
+    #[serde(default)]
+    pub(crate) layers: Vec<Layer>,
+    // playback rate, changes the length and the pitch like a tape running faster or slower
+    #[serde(default = "default_speed")]
+    pub(crate) speed: f64,
+    // in semitones, changes the pitch without changing the length
+    #[serde(default)]
+    pub(crate) pitch: f64,
+    // every press shifts the pitch by a random amount of up to this many semitones up or down
+    #[serde(default)]
+    pub(crate) random_pitch: f64,
+    // applied one after another to the whole sound including its layers
+    #[serde(default)]
+    pub(crate) effects: Vec<Effect>,
+    // where playback starts, in seconds into the file
+    #[serde(default)]
+    pub(crate) start: Option<f64>,
+    // where playback ends, in seconds into the file
+    #[serde(default)]
+    pub(crate) end: Option<f64>,
+    // what pressing and releasing the button does
+    #[serde(default)]
+    pub(crate) mode: PlaybackMode,
+    // length of the fade in at the start, in seconds
+    #[serde(default)]
+    pub(crate) fade_in: f64,
+    // length of the fade out before the end, in seconds
+    // looping and gated sounds use it when they are stopped instead
+    #[serde(default)]
+    pub(crate) fade_out: f64,
+    #[serde(skip)]
     pub(crate) state: PlayState,
 }
 
+// how a sound reacts to its button
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub(crate) enum PlaybackMode {
+    // plays to the end, pressing the button again does not stop it
+    OneShot,
+    // plays to the end or until the button is pressed again
+    #[default]
+    Toggle,
+    // plays only while the button or its hotkey is held down
+    Gate,
+    // repeats until the button is pressed again
+    Loop,
+}
+
+impl PlaybackMode {
+    pub(crate) const ALL: [PlaybackMode; 4] = [
+        PlaybackMode::OneShot,
+        PlaybackMode::Toggle,
+        PlaybackMode::Gate,
+        PlaybackMode::Loop,
+    ];
+
+    // whether the sound starts over when it reaches its end
+    pub(crate) fn repeats(&self) -> bool {
+        matches!(self, PlaybackMode::Loop)
+    }
+
+    // whether the sound usually ends by being stopped rather than by reaching its end
+    fn stopped_by_user(&self) -> bool {
+        matches!(self, PlaybackMode::Loop | PlaybackMode::Gate)
+    }
+}
+
+impl std::fmt::Display for PlaybackMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            PlaybackMode::OneShot => "one-shot",
+            PlaybackMode::Toggle => "toggle",
+            PlaybackMode::Gate => "gate (play while held)",
+            PlaybackMode::Loop => "loop",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+fn default_speed() -> f64 {
+    1.0
+}
+
+// a number from a saved board, nan and inf count as 0 so they never reach a mixer
+fn finite_or_zero(value: f64) -> f64 {
+    if value.is_finite() {
+        value
+    } else {
+        0.0
+    }
+}
+
+// a time in seconds from a saved board, negative or infinite times count as none
+fn secs_duration(secs: f64) -> Duration {
+    Duration::try_from_secs_f64(secs.max(0.0)).unwrap_or_default()
+}
+
 #[derive(Clone, Copy)]
 pub(crate) enum PlayerMessage {
     SettingsChange,
     Stop,
+    Pause,
+    Resume,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub(crate) enum PlayState {
-    Playing,
+    Playing {
+        position: Duration,
+        duration: Option<Duration>,
+    },
+    Paused {
+        position: Duration,
+        duration: Option<Duration>,
+    },
+    #[default]
     Stopped,
+    // pressed while an output has no device and playback is paused, nothing was played
+    NoOutput,
+    // the sound could not be played, the player has ended
+    Error(String),
+}
+
+impl PlayState {
+    // how much of the sound has been played, between 0 and 1
+    pub(crate) fn progress(&self) -> f32 {
+        match self {
+            PlayState::Playing {
+                position,
+                duration: Some(duration),
+            }
+            | PlayState::Paused {
+                position,
+                duration: Some(duration),
+            } if !duration.is_zero() => {
+                (position.as_secs_f32() / duration.as_secs_f32()).min(1.0)
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+// counts the samples consumed by an output so the player can report its position
+struct Tracked<S> {
+    inner: S,
+    played: Arc<AtomicUsize>,
+}
+
+impl<S: Source<Item = f32>> Iterator for Tracked<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.inner.next()?;
+        self.played.fetch_add(1, Ordering::Relaxed);
+        Some(sample)
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Tracked<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
 }
 
 impl Sound {
-    pub(crate) fn new(p: String) -> Self {
+    pub(crate) fn new(paths: Vec<String>) -> Self {
         Self {
-            file_path: p,
+            files: paths.into_iter().map(SoundFile::new).collect(),
+            variation: Default::default(),
+            layers: vec![],
+            speed: default_speed(),
+            pitch: 0.0,
+            random_pitch: 0.0,
+            effects: vec![],
+            start: None,
+            end: None,
+            mode: Default::default(),
+            fade_in: 0.0,
+            fade_out: 0.0,
             state: PlayState::Stopped,
         }
     }
 
-    // plays the sound file associated with the Sound using the default audio output
+    // every file of the sound including the ones of its layers
+    pub(crate) fn all_files(&self) -> impl Iterator<Item = &SoundFile> {
+        self.files
+            .iter()
+            .chain(self.layers.iter().map(|layer| &layer.file))
+    }
+
+    pub(crate) fn all_files_mut(&mut self) -> impl Iterator<Item = &mut SoundFile> {
+        self.files
+            .iter_mut()
+            .chain(self.layers.iter_mut().map(|layer| &mut layer.file))
+    }
+
+    // the paths of all files of the sound including the ones of its layers
+    pub(crate) fn paths(&self) -> impl Iterator<Item = &String> {
+        self.all_files().map(|file| &file.path)
+    }
+
+    // index of the file to play next, last is the index of the file played by the previous press
+    pub(crate) fn pick_file(&self, last: Option<usize>) -> usize {
+        let count = self.files.len().max(1);
+        match (self.variation, last) {
+            (VariationMode::RoundRobin, Some(last)) => (last + 1) % count,
+            (VariationMode::RoundRobin, None) => 0,
+            //skip over the last file so it does not play twice in a row
+            (VariationMode::Random, Some(last)) if count > 1 && last < count => {
+                let index = rand::thread_rng().gen_range(0..count - 1);
+                if index >= last {
+                    index + 1
+                } else {
+                    index
+                }
+            }
+            (VariationMode::Random, _) => rand::thread_rng().gen_range(0..count),
+        }
+    }
+
+    // volume of every output bus for a file of this sound, volume is the button's own volume from 0 to 100
+    fn output_volumes(&self, file: &SoundFile, settings: &AudioSettings, volume: i32) -> Vec<f32> {
+        let gain = file.normalization_gain(settings) * volume as f32 / 100.0;
+        settings
+            .outputs
+            .iter()
+            .map(|bus| if bus.muted { 0.0 } else { bus.volume as f32 / 100.0 * gain })
+            .collect()
+    }
+
+    // playback rate limited to what still sounds like the file
+    pub(crate) fn clamped_speed(&self) -> f64 {
+        if self.speed > 0.0 {
+            self.speed.clamp(MIN_SPEED, MAX_SPEED)
+        } else {
+            1.0
+        }
+    }
+
+    // the pitch shift set for the sound in semitones, without the random variation
+    pub(crate) fn base_pitch(&self) -> f64 {
+        finite_or_zero(self.pitch).clamp(-MAX_PITCH, MAX_PITCH)
+    }
+
+    // how far a press may shift the pitch up or down at random, in semitones
+    pub(crate) fn pitch_range(&self) -> f64 {
+        finite_or_zero(self.random_pitch).abs().min(MAX_PITCH)
+    }
+
+    // the pitch shift of one press in semitones, including its random variation
+    fn press_pitch(&self) -> f64 {
+        let range = self.pitch_range();
+        let variation = if range > 0.0 {
+            rand::thread_rng().gen_range(-range..=range)
+        } else {
+            0.0
+        };
+        (finite_or_zero(self.pitch) + variation).clamp(-MAX_PITCH, MAX_PITCH)
+    }
+
+    // how long the fade out is when the sound gets stopped
+    fn stop_fade(&self) -> Duration {
+        if self.mode.stopped_by_user() && self.fade_out > 0.0 {
+            secs_duration(self.fade_out)
+        } else {
+            STOP_FADE
+        }
+    }
+
+    // the source one output plays for this sound, trimmed, looped, layered, pitched, effected and faded according to its settings
+    // pitch is the shift of this press in semitones, the output volume already contains the normalization of file, so the layers are only corrected relative to it
+    fn source(
+        &self,
+        file: &SoundFile,
+        decoded: &DecodedSound,
+        layers: &[(&Layer, DecodedSound)],
+        settings: &AudioSettings,
+        pitch: f64,
+        stop: StopHandle,
+    ) -> Envelope<LayeredSource> {
+        let mut main = decoded.source(self.start, self.end);
+        if self.mode.repeats() {
+            main = main.looped();
+        }
+
+        let file_gain = file.normalization_gain(settings);
+        let mut source: LayeredSource = Box::new(main);
+        for (layer, layer_decoded) in layers {
+            let gain_db = finite_or_zero(layer.gain).clamp(-MAX_LAYER_GAIN_DB, MAX_LAYER_GAIN_DB);
+            let gain = layer.file.normalization_gain(settings) / file_gain * 10f64.powf(gain_db / 20.0) as f32;
+            let layer_source = layer_decoded
+                .source(None, None)
+                .amplify(gain)
+                .delay(secs_duration(finite_or_zero(layer.offset)));
+            source = Box::new(source.mix(layer_source));
+        }
+
+        if pitch != 0.0 {
+            source = Box::new(PitchShift::new(source, pitch));
+        }
+        let speed = self.clamped_speed();
+        if speed != 1.0 {
+            source = Box::new(source.speed(speed as f32));
+        }
+        for effect in self.effects.iter() {
+            source = Box::new(Effected::new(source, *effect));
+        }
+
+        Envelope::new(
+            source,
+            secs_duration(self.fade_in),
+            secs_duration(self.fade_out),
+            self.stop_fade(),
+            stop,
+        )
+    }
+
+    // decodes a file of the sound and the files of its layers, fails if any of them can not be loaded
+    fn load(
+        &self,
+        file: &SoundFile,
+        cache: &Arc<Mutex<SampleCache>>,
+    ) -> Result<Loaded<'_>, String> {
+        let decoded = sample_cache::load(cache, &file.path)?;
+        let layers = self
+            .layers
+            .iter()
+            .map(|layer| Ok((layer, sample_cache::load(cache, &layer.file.path)?)))
+            .collect::<Result<_, String>>()?;
+        Ok((decoded, layers))
+    }
+
+    // renders what the sound plays for the file at file_index without any output device
+    // trim, layers, pitch, speed, effects, fades, normalization and the button's volume from 0 to 100 are applied,
+    // the random pitch variation is not and a looping sound is rendered once
+    pub(crate) fn render(
+        &self,
+        file_index: usize,
+        settings: &AudioSettings,
+        cache: &Arc<Mutex<SampleCache>>,
+        volume: i32,
+    ) -> Result<Rendered, String> {
+        let mut sound = self.clone();
+        if sound.mode.repeats() {
+            sound.mode = PlaybackMode::Toggle;
+        }
+        let file = sound.files.get(file_index).ok_or("the button has no file")?;
+        let (decoded, layers) = sound.load(file, cache)?;
+
+        let gain = file.normalization_gain(settings) * volume as f32 / 100.0;
+        let source = sound
+            .source(file, &decoded, &layers, settings, sound.base_pitch(), StopHandle::default())
+            .amplify(gain);
+        Ok(Rendered::from_source(source))
+    }
+
+    // plays the file at file_index of the Sound on the outputs kept open by the engine
     // returns a channel Sender to send messages to the player and a receiver to receive messages from the player
-    pub(crate) fn play( &self, settings: Arc<Mutex<AudioSettings>>) -> (Sender<PlayerMessage>, Receiver<PlayState>) {
+    pub(crate) fn play(
+        &self,
+        file_index: usize,
+        settings: Arc<Mutex<AudioSettings>>,
+        engine: Arc<Mutex<AudioEngine>>,
+        cache: Arc<Mutex<SampleCache>>,
+        volume: Arc<Mutex<i32>>,
+    ) -> (Sender<PlayerMessage>, Receiver<PlayState>) {
         let (tx_player_as_receiver, rx_player_as_receiver) = mpsc::channel();
         let (tx_player_as_sender, rx_player_as_sender) = mpsc::channel();
 
-        let path = self.file_path.clone();
+        let sound = self.clone();
+        let file = self.files.get(file_index).cloned();
+        //all outputs have to be shifted by the same random amount
+        let pitch = self.press_pitch();
 
         let _thread_handle = thread::spawn(move || {
-            //get devices from names
-            let out_name = settings.lock().unwrap().out1_dev_name.clone();
-            let out_name2 = settings.lock().unwrap().out2_dev_name.clone();
-            let mut out_dev1 = rodio::cpal::default_host().default_output_device().unwrap();
-            let mut out_dev2 =  rodio::cpal::default_host().default_output_device().unwrap();
-
-
-            for dev in rodio::cpal::default_host().output_devices().unwrap(){
-                if let Ok(name) = dev.name(){
-                    let mut out1_set = false;
-                    let mut out2_set = false;
-                    if name == out_name{
-                        out_dev1 = dev;
-                        out1_set = true;
-                    }
-                    else if name == out_name2{
-                        out_dev2 = dev;
-                        out2_set = true;
+            //whatever goes wrong while playing, the button has to hear about it
+            let played = panic::catch_unwind(AssertUnwindSafe(|| {
+                //get the mixers of the already opened outputs, only the voice has to be added to them
+                //there are none while playback waits for a missing device
+                let current_settings = settings.lock().unwrap().clone();
+                let outputs = engine.lock().unwrap().outputs(&current_settings);
+                let mixers = match outputs {
+                    Some(outputs) => outputs,
+                    None => {
+                        let _ = tx_player_as_sender.send(PlayState::NoOutput);
+                        return;
                     }
-                    if out1_set && out2_set{
-                        break;
-                    }
-                }
-            }
+                };
 
+                //all outputs play from the same decoded samples
+                let loaded = file
+                    .as_ref()
+                    .ok_or_else(|| "the button has no file".to_string())
+                    .and_then(|file| Ok((file, sound.load(file, &cache)?)));
+                let (file, (decoded, layers)) = match loaded {
+                    Ok(loaded) => loaded,
+                    Err(reason) => {
+                        let _ = tx_player_as_sender.send(PlayState::Error(reason));
+                        return;
+                    }
+                };
 
-            let (_stream, out1_stream_handle) = OutputStream::try_from_device(&out_dev1).unwrap();
-            let (_stream, out2_stream_handle) = OutputStream::try_from_device(&out_dev2).unwrap();
-            let out1_file_buf = BufReader::new(File::open(path.clone()).unwrap());
-            let out2_file_buf = BufReader::new(File::open(path).unwrap());
-            let out1_source = Decoder::new(out1_file_buf).unwrap();
-            let out2_source = Decoder::new(out2_file_buf).unwrap();
-            let out1_sink = Sink::try_new(&out1_stream_handle).unwrap();
-            let out2_sink = Sink::try_new(&out2_stream_handle).unwrap();
+                let stop = StopHandle::default();
+                //every output counts what it played, an output removed while playing stops counting
+                let played: Vec<Arc<AtomicUsize>> = mixers.iter().map(|_| Arc::new(AtomicUsize::new(0))).collect();
+                let sources: Vec<_> = played
+                    .iter()
+                    .map(|played| Tracked {
+                        inner: sound.source(file, &decoded, &layers, &current_settings, pitch, stop.clone()),
+                        played: played.clone(),
+                    })
+                    .collect();
+                let samples_per_sec = match sources.first() {
+                    Some(source) => source.channels() as f64 * source.sample_rate() as f64,
+                    None => 1.0,
+                };
+                //a looping sound reports its position within the current pass
+                let duration = if sound.mode.repeats() {
+                    decoded
+                        .source(sound.start, sound.end)
+                        .total_duration()
+                        .map(|pass| pass.div_f64(sound.clamped_speed()))
+                } else {
+                    sources.first().and_then(|source| source.total_duration())
+                };
+                let state = |paused: bool| {
+                    let played = played.iter().map(|played| played.load(Ordering::Relaxed)).max().unwrap_or(0);
+                    let mut position = Duration::from_secs_f64(played as f64 / samples_per_sec);
+                    if let Some(duration) = duration.filter(|dur| sound.mode.repeats() && !dur.is_zero()) {
+                        position = Duration::from_secs_f64(
+                            position.as_secs_f64() % duration.as_secs_f64(),
+                        );
+                    }
+                    if paused {
+                        PlayState::Paused { position, duration }
+                    } else {
+                        PlayState::Playing { position, duration }
+                    }
+                };
+                let mut paused = false;
+                let volumes = sound.output_volumes(file, &current_settings, *volume.lock().unwrap());
+                let voices: Vec<(Arc<Mixer>, Voice)> = mixers
+                    .into_iter()
+                    .zip(sources)
+                    .zip(volumes)
+                    .map(|((mixer, source), volume)| {
+                        let voice = mixer.play(Box::new(source), volume);
+                        (mixer, voice)
+                    })
+                    .collect();
+                let _ = tx_player_as_sender.send(state(paused));
 
-            let play_duration = match out2_source.total_duration() {
-                Some(dur) => dur,
-                None => Duration::new(20, 0), //dummy duration
-            };
+                //set once stopping, in case the outputs stop pulling samples before the fade finished
+                let mut stop_deadline: Option<Instant> = None;
 
-            let start_time = SystemTime::now();
+                //playback has ended once the voices on all outputs have drained
+                while !voices.iter().all(|(_, voice)| voice.is_done()) {
+                    if stop_deadline.is_some_and(|deadline| Instant::now() > deadline) {
+                        break;
+                    }
 
-            if !settings.lock().unwrap().output2_muted {
-                out2_sink.set_volume(settings.lock().unwrap().output2_slider_value as f32 / 100.0);
-            } else {
-                out2_sink.set_volume(0.0);
-            }
+                    match rx_player_as_receiver.recv_timeout(POLL_INTERVAL) {
+                        //a paused sound is silent already, so there is nothing to fade
+                        Ok(PlayerMessage::Stop) if paused => break,
 
-            if !settings.lock().unwrap().output1_muted {
-                out1_sink.set_volume(settings.lock().unwrap().output1_slider_value as f32 / 100.0);
-            } else {
-                out1_sink.set_volume(0.0);
-            }
+                        Ok(PlayerMessage::Stop) => {
+                            stop.stop();
+                            stop_deadline = Some(Instant::now() + sound.stop_fade() + STOP_GRACE);
+                        }
 
-            out2_sink.append(out2_source);
-            out1_sink.append(out1_source);
-            tx_player_as_sender.send(PlayState::Playing);
+                        Ok(PlayerMessage::SettingsChange) => {
+                            //never hold the settings while working with them, a panic here must not poison them
+                            let current_settings = settings.lock().unwrap().clone();
+                            let volumes = sound.output_volumes(file, &current_settings, *volume.lock().unwrap());
+                            //outputs may have been removed, a voice takes the volume of the bus its mixer belongs to now
+                            let mixers = engine.lock().unwrap().mixers();
+                            for (mixer, voice) in voices.iter() {
+                                let bus = mixers.iter().position(|bus_mixer| Arc::ptr_eq(bus_mixer, mixer));
+                                if let Some(volume) = bus.and_then(|bus| volumes.get(bus)) {
+                                    voice.set_volume(*volume);
+                                }
+                            }
+                        }
 
-            while start_time.elapsed().unwrap() < play_duration && !out1_sink.empty() {
-                let msg = rx_player_as_receiver
-                    .recv_timeout(play_duration - start_time.elapsed().unwrap());
+                        Ok(PlayerMessage::Pause) => {
+                            voices.iter().for_each(|(_, voice)| voice.pause());
+                            paused = true;
+                            let _ = tx_player_as_sender.send(state(paused));
+                        }
 
-                if let Ok(msg) = msg {
-                    match msg {
-                        PlayerMessage::Stop => {
-                            out1_sink.stop();
-                            out2_sink.stop();
-                            tx_player_as_sender.send(PlayState::Stopped);
+                        Ok(PlayerMessage::Resume) => {
+                            voices.iter().for_each(|(_, voice)| voice.resume());
+                            paused = false;
+                            let _ = tx_player_as_sender.send(state(paused));
                         }
 
-                        PlayerMessage::SettingsChange => {
-                            let settings = settings.lock().unwrap();
-                            if !settings.output1_muted {
-                                out1_sink.set_volume(settings.output1_slider_value as f32 / 100.0);
-                            } else {
-                                out1_sink.set_volume(0.0);
-                            }
+                        Err(RecvTimeoutError::Timeout) => {
+                            let _ = tx_player_as_sender.send(state(paused));
+                        }
 
-                            if !settings.output2_muted {
-                                out2_sink.set_volume(settings.output2_slider_value as f32 / 100.0);
-                            } else {
-                                out2_sink.set_volume(0.0);
-                            }
+                        //the button owning this player is gone, a fade it started still has to play out
+                        Err(RecvTimeoutError::Disconnected) if stop_deadline.is_some() => {
+                            thread::sleep(POLL_INTERVAL)
                         }
+
+                        //the button owning this player is gone
+                        Err(RecvTimeoutError::Disconnected) => break,
                     }
                 }
+                voices.iter().for_each(|(_, voice)| voice.stop());
+                let _ = tx_player_as_sender.send(PlayState::Stopped);
+            }));
+            if played.is_err() {
+                let _ = tx_player_as_sender.send(PlayState::Error("playback failed unexpectedly".to_string()));
             }
-            out2_sink.stop();
-            out1_sink.stop();
-            tx_player_as_sender.send(PlayState::Stopped)
         });
 
         (tx_player_as_receiver, rx_player_as_sender)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_settings::{DeviceLossPolicy, OutputBus};
+    use crate::backend::AudioBackend;
+    use crate::fixtures::TempFile;
+    use crate::mixer::{CHANNELS, SAMPLE_RATE};
+    use crate::recording::RecordingBackend;
+
+    // a mono file at 1000 frames per second, so a frame is a millisecond
+    const RATE: u32 = 1000;
+
+    fn constant_wav(value: f32, frames: usize) -> TempFile {
+        TempFile::wav(1, RATE, &vec![value; frames])
+    }
+
+    fn assert_near(actual: f32, expected: f32, what: &str) {
+        assert!((actual - expected).abs() < 1e-3, "{}: {} instead of {}", what, actual, expected);
+    }
+
+    #[test]
+    fn render_applies_trim_fades_layers_and_volume() {
+        let main = constant_wav(0.5, 2000);
+        let layer = constant_wav(0.25, 500);
+        let mut sound = Sound::new(vec![main.path_string()]);
+        sound.start = Some(0.25);
+        sound.end = Some(1.75);
+        sound.fade_in = 0.1;
+        sound.fade_out = 0.1;
+        sound.layers = vec![Layer {
+            file: SoundFile::new(layer.path_string()),
+            gain: 0.0,
+            offset: 0.5,
+        }];
+
+        let cache = Arc::new(Mutex::new(SampleCache::default()));
+        let rendered = sound.render(0, &AudioSettings::default(), &cache, 50).unwrap();
+        assert_eq!(rendered.channels, 1);
+        assert_eq!(rendered.sample_rate, RATE);
+        //the trimmed file is 1.5 seconds long, the layer ends within it
+        assert_eq!(rendered.samples.len(), 1500);
+
+        let samples = &rendered.samples;
+        assert_near(samples[0], 0.0, "start of the fade in");
+        assert_near(samples[50], 0.125, "middle of the fade in");
+        assert_near(samples[200], 0.25, "file at half volume");
+        assert_near(samples[700], 0.375, "file and layer at half volume");
+        assert_near(samples[1200], 0.25, "file after the layer ended");
+        assert_near(samples[1450], 0.125, "middle of the fade out");
+    }
+
+    #[test]
+    fn render_extends_to_the_end_of_a_late_layer() {
+        let main = constant_wav(0.5, 1000);
+        let layer = constant_wav(0.25, 1000);
+        let mut sound = Sound::new(vec![main.path_string()]);
+        sound.layers = vec![Layer {
+            file: SoundFile::new(layer.path_string()),
+            gain: -6.0,
+            offset: 0.5,
+        }];
+
+        let cache = Arc::new(Mutex::new(SampleCache::default()));
+        let rendered = sound.render(0, &AudioSettings::default(), &cache, 100).unwrap();
+        assert_eq!(rendered.samples.len(), 1500);
+        let layer_gain = 10f32.powf(-6.0 / 20.0);
+        assert_near(rendered.samples[250], 0.5, "file alone");
+        assert_near(rendered.samples[750], 0.5 + 0.25 * layer_gain, "file and layer");
+        assert_near(rendered.samples[1250], 0.25 * layer_gain, "layer alone");
+    }
+
+    fn bus(dev_name: &str, volume: i32, muted: bool) -> OutputBus {
+        OutputBus {
+            dev_name: dev_name.to_string(),
+            volume,
+            muted,
+        }
+    }
+
+    // a second of a stereo ramp in the format the mixers mix, so it reaches the outputs unchanged
+    fn ramp() -> Vec<f32> {
+        (0..SAMPLE_RATE as usize * CHANNELS as usize)
+            .map(|index| (index % 200) as f32 / 200.0 - 0.5)
+            .collect()
+    }
+
+    // a sound playing the file on outputs "a" and "b" of a recording backend
+    struct Playing {
+        backend: Arc<RecordingBackend>,
+        settings: Arc<Mutex<AudioSettings>>,
+        player: Sender<PlayerMessage>,
+        samples: Vec<f32>,
+        // the file has to exist until it got decoded
+        _file: TempFile,
+    }
+
+    impl Playing {
+        fn start(outputs: Vec<OutputBus>) -> Self {
+            let samples = ramp();
+            let file = TempFile::wav(CHANNELS, SAMPLE_RATE, &samples);
+            let backend = Arc::new(RecordingBackend::new(vec!["a".to_string(), "b".to_string()], None));
+            let engine = Arc::new(Mutex::new(AudioEngine::new(backend.clone())));
+            let mut settings = AudioSettings::default();
+            settings.outputs = outputs;
+            settings.normalize_loudness = false;
+            let settings = Arc::new(Mutex::new(settings));
+
+            let sound = Sound::new(vec![file.path_string()]);
+            let cache = Arc::new(Mutex::new(SampleCache::default()));
+            let (player, states) = sound.play(0, settings.clone(), engine, cache, Arc::new(Mutex::new(100)));
+            //the voices are on the outputs once the player reports playing
+            loop {
+                match states.recv_timeout(Duration::from_secs(5)).unwrap() {
+                    PlayState::Playing { .. } => break,
+                    PlayState::Error(reason) => panic!("{}", reason),
+                    _ => {}
+                }
+            }
+            Self {
+                backend,
+                settings,
+                player,
+                samples,
+                _file: file,
+            }
+        }
+
+        fn record(&self, frames: usize) -> (Vec<f32>, Vec<f32>) {
+            self.backend.advance(frames);
+            (self.backend.take_recorded("a"), self.backend.take_recorded("b"))
+        }
+    }
+
+    // rodio 0.14 decodes the float fixtures to 16 bit samples, so they only match to about 1/32768
+    const TOLERANCE: f32 = 1e-4;
+
+    fn assert_scaled(recorded: &[f32], samples: &[f32], volume: f32) {
+        assert_eq!(recorded.len(), samples.len());
+        for (index, (recorded, sample)) in recorded.iter().zip(samples).enumerate() {
+            assert!(
+                (recorded - sample * volume).abs() < TOLERANCE,
+                "sample {}: {} instead of {}",
+                index,
+                recorded,
+                sample * volume
+            );
+        }
+    }
+
+    #[test]
+    fn outputs_play_the_file_at_their_volume() {
+        let playing = Playing::start(vec![bus("a", 50, false), bus("b", 100, false)]);
+        let (a, b) = playing.record(1000);
+        assert_scaled(&a, &playing.samples[..2000], 0.5);
+        assert_scaled(&b, &playing.samples[..2000], 1.0);
+    }
+
+    #[test]
+    fn muted_output_records_silence() {
+        let playing = Playing::start(vec![bus("a", 100, false), bus("b", 100, true)]);
+        let (a, b) = playing.record(1000);
+        assert_scaled(&a, &playing.samples[..2000], 1.0);
+        assert_eq!(b.len(), 2000);
+        assert!(b.iter().all(|sample| *sample == 0.0));
+    }
+
+    #[test]
+    fn settings_change_applies_the_new_volume_while_playing() {
+        let playing = Playing::start(vec![bus("a", 100, false), bus("b", 100, false)]);
+        let (a, _) = playing.record(1000);
+        assert_scaled(&a, &playing.samples[..2000], 1.0);
+
+        playing.settings.lock().unwrap().outputs[0].volume = 25;
+        playing.player.send(PlayerMessage::SettingsChange).unwrap();
+
+        //the player applies it on its own thread, wait until a frame comes out at the new volume
+        let mut position = 2000;
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let (a, _) = playing.record(1);
+            let sample = playing.samples[position + 1];
+            position += 2;
+            //near zero both volumes give the same sample
+            if sample.abs() > 0.1 && (a[1] - sample * 0.25).abs() < TOLERANCE {
+                break;
+            }
+            assert!(Instant::now() < deadline, "the volume was never applied");
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let (a, b) = playing.record(1000);
+        assert_scaled(&a, &playing.samples[position..position + 2000], 0.25);
+        assert_scaled(&b, &playing.samples[position..position + 2000], 1.0);
+    }
+
+    #[test]
+    fn layers_that_are_not_numbers_play_as_if_unset() {
+        //quiet enough that the capped boost does not clip
+        let main = constant_wav(0.01, 1000);
+        let layer = constant_wav(0.001, 500);
+        let mut sound = Sound::new(vec![main.path_string()]);
+        sound.layers = vec![
+            Layer {
+                file: SoundFile::new(layer.path_string()),
+                gain: f64::NAN,
+                offset: f64::INFINITY,
+            },
+            Layer {
+                file: SoundFile::new(layer.path_string()),
+                gain: f64::INFINITY,
+                offset: f64::NAN,
+            },
+            Layer {
+                file: SoundFile::new(layer.path_string()),
+                gain: 1000.0,
+                offset: 0.0,
+            },
+        ];
+
+        let cache = Arc::new(Mutex::new(SampleCache::default()));
+        let rendered = sound.render(0, &AudioSettings::default(), &cache, 100).unwrap();
+        assert_eq!(rendered.samples.len(), 1000);
+        assert!(rendered.samples.iter().all(|sample| sample.is_finite()));
+        //nan and inf count as 0, too large a gain is capped
+        let decoded = |file: &TempFile| DecodedSound::decode(&file.path_string()).unwrap().samples()[0];
+        let capped = 10f32.powf(MAX_LAYER_GAIN_DB as f32 / 20.0);
+        let all = decoded(&main) + decoded(&layer) * (2.0 + capped);
+        assert_near(rendered.samples[250], all, "file and all layers");
+        assert_near(rendered.samples[750], decoded(&main), "file alone");
+    }
+
+    #[test]
+    fn pitch_that_is_not_a_number_plays_unshifted() {
+        let mut sound = Sound::new(vec![]);
+        sound.pitch = f64::NAN;
+        sound.random_pitch = f64::NAN;
+        assert_eq!(sound.press_pitch(), 0.0);
+        sound.pitch = f64::INFINITY;
+        sound.random_pitch = f64::INFINITY;
+        assert_eq!(sound.press_pitch(), 0.0);
+        assert_eq!(sound.base_pitch(), 0.0);
+        sound.pitch = 100.0;
+        assert_eq!(sound.press_pitch(), MAX_PITCH);
+        assert_eq!(sound.base_pitch(), MAX_PITCH);
+    }
+
+    #[test]
+    fn press_while_paused_for_a_missing_output_is_not_an_error() {
+        let file = constant_wav(0.5, 100);
+        let backend = Arc::new(RecordingBackend::new(vec!["a".to_string()], None));
+        let engine = Arc::new(Mutex::new(AudioEngine::new(backend.clone())));
+        let mut settings = AudioSettings::default();
+        settings.outputs = vec![bus("a", 100, false)];
+        settings.device_loss = DeviceLossPolicy::Pause;
+        engine.lock().unwrap().sync_devices(&settings);
+        backend.unplug("a");
+        engine.lock().unwrap().check_devices(&backend.output_device_names(), &settings);
+
+        let sound = Sound::new(vec![file.path_string()]);
+        let cache = Arc::new(Mutex::new(SampleCache::default()));
+        let settings = Arc::new(Mutex::new(settings));
+        let (_player, states) = sound.play(0, settings, engine, cache, Arc::new(Mutex::new(100)));
+        let state = states.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(matches!(state, PlayState::NoOutput), "{:?}", state);
+    }
+}
@@ -0,0 +1,42 @@
+use iced_native::futures::channel::mpsc;
+use iced_native::futures::stream::{BoxStream, StreamExt};
+use iced_native::subscription::Recipe;
+use iced_native::{event, Event, Hasher, Subscription};
+use std::hash::Hash;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// returns a subscription that produces the current time every interval
+// the default executor has no timers, so the ticks come from a sleeping thread
+pub(crate) fn every(interval: Duration) -> Subscription<Instant> {
+    Subscription::from_recipe(Every(interval))
+}
+
+struct Every(Duration);
+
+impl Recipe<Hasher, (Event, event::Status)> for Every {
+    type Output = Instant;
+
+    fn hash(&self, state: &mut Hasher) {
+        std::any::TypeId::of::<Self>().hash(state);
+        self.0.hash(state);
+    }
+
+    fn stream(
+        self: Box<Self>,
+        _input: BoxStream<'static, (Event, event::Status)>,
+    ) -> BoxStream<'static, Self::Output> {
+        let (tx, rx) = mpsc::unbounded();
+        let interval = self.0;
+
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            //the subscription was dropped
+            if tx.unbounded_send(Instant::now()).is_err() {
+                break;
+            }
+        });
+
+        rx.boxed()
+    }
+}